@@ -0,0 +1,292 @@
+//
+// Climb and descent detection tools
+//
+// The altitudes are smoothed over the window (m along the track), then the turning points are taken
+// with the hysteresis of merge_dip (m), so dips and bumps smaller than merge_dip stay inside one climb.
+// Climbs and descents with less than min_gain (m) or flatter than min_grade (%) on average are dropped.
+// Maximum grade is the steepest average over grade_window (m), grades of descents are positive.
+//
+// -- Category -- score = length (m) * average grade (%)
+// [Cat4] 8000 ~  [Cat3] 16000 ~  [Cat2] 32000 ~  [Cat1] 64000 ~  [Hc] 80000 ~
+//
+pub mod climb {
+    use crate::geometry::geometry_core::LatLon;
+    use crate::gpx::gpx::{PointType, Track, TrackPoint, TrackSegment};
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum ClimbKind {
+        Climb,
+        Descent,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum ClimbCategory {
+        Uncategorized,
+        Cat4,
+        Cat3,
+        Cat2,
+        Cat1,
+        Hc,
+    }
+
+    impl ClimbCategory {
+        pub fn from_score(score: f64) -> ClimbCategory {
+            if score >= 80000f64 { ClimbCategory::Hc }
+            else if score >= 64000f64 { ClimbCategory::Cat1 }
+            else if score >= 32000f64 { ClimbCategory::Cat2 }
+            else if score >= 16000f64 { ClimbCategory::Cat3 }
+            else if score >= 8000f64 { ClimbCategory::Cat4 }
+            else { ClimbCategory::Uncategorized }
+        }
+
+        pub fn as_str(&self) -> &str {
+            match self {
+                ClimbCategory::Uncategorized => {"Uncategorized"},
+                ClimbCategory::Cat4 => {"Cat 4"},
+                ClimbCategory::Cat3 => {"Cat 3"},
+                ClimbCategory::Cat2 => {"Cat 2"},
+                ClimbCategory::Cat1 => {"Cat 1"},
+                ClimbCategory::Hc => {"HC"},
+            }
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct Climb {
+        pub kind: ClimbKind,
+        pub start: usize,               // Point index in the segment
+        pub end: usize,
+        pub start_location: LatLon,
+        pub end_location: LatLon,
+        pub start_distance: f64,        // m from the first point
+        pub length: f64,                // m
+        pub gain: f64,                  // m, positive for descents too
+        pub average_grade: f64,         // %
+        pub max_grade: f64,             // %
+        pub category: ClimbCategory,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct ClimbDetector {
+        pub window: f64,        // m, smoothing
+        pub merge_dip: f64,     // m
+        pub min_gain: f64,      // m
+        pub min_grade: f64,     // %
+        pub grade_window: f64,  // m
+    }
+
+    impl ClimbDetector {
+        pub fn new() -> ClimbDetector {
+            Self {window: 100f64, merge_dip: 10f64, min_gain: 30f64, min_grade: 3f64, grade_window: 100f64}
+        }
+
+        // Climbs and descents in the order along the segment
+        pub fn detect(&self, segment: &TrackSegment) -> Vec<Climb> {
+            let altitudes = segment.smoothed_altitudes(self.window);
+            let mut along = Vec::with_capacity(segment.points.len());
+            let mut d = 0f64;
+            for p in &segment.points {
+                d += p.distance();
+                along.push(d);
+            }
+
+            let turns = turning_points(&altitudes, self.merge_dip);
+            let mut climbs = Vec::new();
+            for w in turns.windows(2) {
+                let (a, b) = (w[0].1, w[1].0);
+                let length = along[b] - along[a];
+                let rise = altitudes[b] - altitudes[a];
+                if length <= 0f64 || rise.abs() < self.min_gain {
+                    continue;
+                }
+                let average_grade = rise.abs() / length * 100f64;
+                if average_grade < self.min_grade {
+                    continue;
+                }
+                let kind = if rise > 0f64 { ClimbKind::Climb } else { ClimbKind::Descent };
+                let max_grade = self.max_grade(&altitudes, &along, a, b, rise.signum()).max(average_grade);
+                climbs.push(Climb {
+                    kind,
+                    start: a,
+                    end: b,
+                    start_location: segment.points[a].point.location,
+                    end_location: segment.points[b].point.location,
+                    start_distance: along[a],
+                    length,
+                    gain: rise.abs(),
+                    average_grade,
+                    max_grade,
+                    category: ClimbCategory::from_score(length * average_grade),
+                });
+            }
+            climbs
+        }
+
+        // Waypoints at the start and the top of each climb, named "Climb N start" / "Climb N top"
+        pub fn waypoints(&self, segment: &TrackSegment) -> Vec<TrackPoint> {
+            let mut points = Vec::new();
+            let climbs = self.detect(segment);
+            for (n, c) in climbs.iter().filter(|c| c.kind == ClimbKind::Climb).enumerate() {
+                let summary = format!("{} {:.1}km +{:.0}m {:.1}% (max {:.1}%)",
+                                      c.category.as_str(), c.length / 1000f64, c.gain, c.average_grade, c.max_grade);
+                for (index, label, point_type) in [(c.start, "start", PointType::Note), (c.end, "top", PointType::Summit)] {
+                    let mut p = segment.points[index].point.clone();
+                    p.name = format!("Climb {} {}", n + 1, label);
+                    p.comment = summary.clone();
+                    p.point_type = vec![point_type];
+                    points.push(p);
+                }
+            }
+            points
+        }
+
+        // Steepest grade (%) over grade_window between a and b in the direction of the sign
+        fn max_grade(&self, altitudes: &[f64], along: &[f64], a: usize, b: usize, sign: f64) -> f64 {
+            let mut max = f64::NAN;
+            let mut j = a;
+            for i in a..b {
+                while j < b && along[j] - along[i] < self.grade_window {
+                    j += 1;
+                }
+                let length = along[j] - along[i];
+                if length < self.grade_window {
+                    break;
+                }
+                let grade = sign * (altitudes[j] - altitudes[i]) / length * 100f64;
+                if !grade.is_nan() {
+                    max = max.max(grade);
+                }
+            }
+            max
+        }
+    }
+
+    impl Track {
+        // Add the climb waypoints of every segment, returns the number of the waypoints added
+        pub fn add_climb_waypoints(self: &mut Self, detector: &ClimbDetector) -> usize {
+            let mut points = Vec::new();
+            for r in &self.routes {
+                for s in &r.segments {
+                    points.extend(detector.waypoints(s));
+                }
+            }
+            let n = points.len();
+            for p in points {
+                self.add_waypoint(p);
+            }
+            n
+        }
+    }
+
+    // Alternating lowest / highest points as (first, last) index of the level, a turn is fixed after
+    // moving threshold from it, so a climb ends at the first point of a plateau and the next descent starts at the last
+    fn turning_points(altitudes: &[f64], threshold: f64) -> Vec<(usize, usize)> {
+        let threshold = threshold.max(f64::MIN_POSITIVE);
+        let known: Vec<usize> = (0..altitudes.len()).filter(|i| !altitudes[*i].is_nan()).collect();
+        if known.len() < 2 {
+            return Vec::new();
+        }
+        let mut turns = Vec::new();
+        let mut direction = 0f64;      // 1 climbing, -1 descending, 0 not decided yet
+        let (mut low, mut high) = ((known[0], known[0]), (known[0], known[0]));
+        let mut extreme = (known[0], known[0]);
+        for &i in &known[1..] {
+            let h = altitudes[i];
+            if direction == 0f64 {
+                // Lowest and highest so far until the first move
+                let (below, above) = (altitudes[low.0] - h, h - altitudes[high.0]);
+                update(&mut low, i, below);
+                update(&mut high, i, above);
+                if h - altitudes[low.0] >= threshold {
+                    turns.push(low);
+                    direction = 1f64;
+                    extreme = (i, i);
+                } else if altitudes[high.0] - h >= threshold {
+                    turns.push(high);
+                    direction = -1f64;
+                    extreme = (i, i);
+                }
+            } else if (altitudes[extreme.0] - h) * direction >= threshold {
+                turns.push(extreme);
+                direction = -direction;
+                extreme = (i, i);
+            } else {
+                let beyond = (h - altitudes[extreme.0]) * direction;
+                update(&mut extreme, i, beyond);
+            }
+        }
+        if direction != 0f64 {
+            turns.push(extreme);
+        }
+        turns
+    }
+
+    // Beyond (positive) the level starts a new level, on the level (0) extends it
+    fn update(level: &mut (usize, usize), i: usize, beyond: f64) {
+        if beyond > 0f64 {
+            *level = (i, i);
+        } else if beyond == 0f64 {
+            level.1 = i;
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::gpx::gpx::TrackRoute;
+
+        // Toward north, a point every 50m, altitude from the function of the distance (m)
+        fn profile(km: f64, altitude: &dyn Fn(f64) -> f64) -> TrackSegment {
+            let mut segment = TrackSegment::new();
+            for i in 0..=(km * 20f64) as usize {
+                let d = i as f64 * 50f64;
+                let mut p = TrackPoint::new(35.0 + d / 111195f64, 139.0);
+                p.altitude = altitude(d);
+                segment.add_point(p);
+            }
+            segment
+        }
+
+        #[test]
+        fn climbs() {
+            // 4km at 6% with a short dip at 2km, 1km flat, 2km down at 10%
+            let segment = profile(7f64, &|d| {
+                if d <= 4000f64 {
+                    500f64 + d * 0.06 - if (1900f64..=2100f64).contains(&d) { 12f64 } else { 0f64 }
+                } else if d <= 5000f64 {
+                    740f64
+                } else {
+                    740f64 - (d - 5000f64) * 0.1
+                }
+            });
+            let detector = ClimbDetector::new();
+            let climbs = detector.detect(&segment);
+            assert_eq!(climbs.len(), 2);
+            let (up, down) = (&climbs[0], &climbs[1]);
+            assert_eq!(up.kind, ClimbKind::Climb);
+            assert!((up.length - 4000f64).abs() < 150f64);
+            assert!((up.gain - 240f64).abs() < 10f64);
+            assert!((up.average_grade - 6f64).abs() < 0.3);
+            assert!(up.max_grade > up.average_grade);
+            // 4000 * 6 = 24000
+            assert_eq!(up.category, ClimbCategory::Cat3);
+            assert_eq!(down.kind, ClimbKind::Descent);
+            assert!((down.gain - 200f64).abs() < 10f64);
+            assert!((down.max_grade - 10f64).abs() < 0.5);
+
+            let points = detector.waypoints(&segment);
+            assert_eq!(points.len(), 2);
+            assert_eq!(points[1].name, "Climb 1 top");
+            assert!(points[1].point_type == vec![PointType::Summit]);
+            assert!(ClimbCategory::from_score(90000f64) > ClimbCategory::Cat1);
+
+            let mut route = TrackRoute::new();
+            route.add_segment(&segment);
+            let mut track = Track::new();
+            track.routes.push(route);
+            assert_eq!(track.add_climb_waypoints(&detector), 2);
+            assert_eq!(track.waypoints.len(), 2);
+            assert_eq!(track.waypoints[0].name, "Climb 1 start");
+        }
+    }
+}
@@ -0,0 +1,291 @@
+
+//
+// Geodetic datum transformation tools
+//
+// -- Datum --
+// [Tokyo] Bessel ellipsoid, the old Japanese datum (about 400m off from JGD2000)
+// [JGD2000] GRS80 ellipsoid, ITRF94
+// [JGD2011] GRS80 ellipsoid, ITRF2008, JGD2000 revised after the 2011 Tohoku earthquake
+// [WGS84] WGS84 ellipsoid, treated as identical to JGD2011 (difference is a few cm)
+//
+// Tokyo <-> JGD2000 uses the TKY2JGD grid when it is supplied and covers the location, else the Helmert parameters.
+// JGD2000 <-> JGD2011 uses the PatchJGD grid when it is supplied, else no correction.
+//
+// Grid files are GSI's parameter file format: 2 header lines and then lines of
+//   MeshCode(1km mesh, 8 digits) dB(sec) dL(sec) [dH(m)]
+//
+pub mod datum {
+    use std::collections::HashMap;
+    use std::fs::File;
+    use std::io;
+    use std::io::{BufRead, BufReader};
+    use std::path::Path;
+    use crate::geodesic::geodesic::Ellipsoid;
+    use crate::geometry::geometry_core::LatLon;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Datum {
+        Tokyo,
+        Jgd2000,
+        Jgd2011,
+        Wgs84,
+    }
+
+    impl Datum {
+        pub fn ellipsoid(&self) -> Ellipsoid {
+            match self {
+                Datum::Tokyo => { Ellipsoid::BESSEL }
+                Datum::Jgd2000 | Datum::Jgd2011 => { Ellipsoid::GRS80 }
+                Datum::Wgs84 => { Ellipsoid::WGS84 }
+            }
+        }
+
+        pub fn as_str(&self) -> &str {
+            match self {
+                Datum::Tokyo => { "Tokyo" }
+                Datum::Jgd2000 => { "JGD2000" }
+                Datum::Jgd2011 => { "JGD2011" }
+                Datum::Wgs84 => { "WGS84" }
+            }
+        }
+    }
+
+    // 7 parameters Helmert transformation (position vector convention)
+    #[derive(Debug, Clone, Copy)]
+    pub struct Helmert {
+        pub tx: f64,    // m
+        pub ty: f64,
+        pub tz: f64,
+        pub rx: f64,    // arc second
+        pub ry: f64,
+        pub rz: f64,
+        pub s: f64,     // ppm
+    }
+
+    impl Helmert {
+        // GSI's parameters from Tokyo Datum to JGD2000
+        pub const TOKYO_JGD2000: Helmert = Helmert{tx: -146.414, ty: 507.337, tz: 680.507, rx: 0f64, ry: 0f64, rz: 0f64, s: 0f64};
+
+        pub fn inverse(&self) -> Helmert {
+            Helmert{tx: -self.tx, ty: -self.ty, tz: -self.tz, rx: -self.rx, ry: -self.ry, rz: -self.rz, s: -self.s}
+        }
+
+        // Transform location and ellipsoidal height on the `from` ellipsoid to the `to` ellipsoid
+        pub fn transform(&self, l: &LatLon, h: f64, from: &Ellipsoid, to: &Ellipsoid) -> (LatLon, f64) {
+            let (x, y, z) = to_ecef(l, h, from);
+            let rx = (self.rx / 3600f64).to_radians();
+            let ry = (self.ry / 3600f64).to_radians();
+            let rz = (self.rz / 3600f64).to_radians();
+            let s = 1f64 + self.s * 1e-6;
+            let x2 = self.tx + s * (x - rz * y + ry * z);
+            let y2 = self.ty + s * (rz * x + y - rx * z);
+            let z2 = self.tz + s * (-ry * x + rx * y + z);
+            from_ecef(x2, y2, z2, to)
+        }
+    }
+
+    fn to_ecef(l: &LatLon, h: f64, e: &Ellipsoid) -> (f64, f64, f64) {
+        let (phi, lambda) = l.radian();
+        let e2 = e.e2();
+        let n = e.a / (1f64 - e2 * phi.sin() * phi.sin()).sqrt();
+        ((n + h) * phi.cos() * lambda.cos(),
+         (n + h) * phi.cos() * lambda.sin(),
+         (n * (1f64 - e2) + h) * phi.sin())
+    }
+
+    fn from_ecef(x: f64, y: f64, z: f64, e: &Ellipsoid) -> (LatLon, f64) {
+        let e2 = e.e2();
+        let p = (x * x + y * y).sqrt();
+        let lambda = y.atan2(x);
+        let mut phi = z.atan2(p * (1f64 - e2));
+        let mut h = 0f64;
+        for _ in 0..10 {
+            let n = e.a / (1f64 - e2 * phi.sin() * phi.sin()).sqrt();
+            h = p / phi.cos() - n;
+            phi = z.atan2(p * (1f64 - e2 * n / (n + h)));
+        }
+        (LatLon::new(phi.to_degrees(), lambda.to_degrees()), h)
+    }
+
+    // Grid index of the 1km (3rd) mesh, 30" in latitude and 45" in longitude
+    fn mesh_index(l: &LatLon) -> (i64, i64) {
+        ((l.lat * 120f64).floor() as i64, ((l.lon - 100f64) * 80f64).floor() as i64)
+    }
+
+    fn mesh_code(i: i64, j: i64) -> u64 {
+        let (p, q, r) = (i / 80, (i % 80) / 10, i % 10);
+        let (u, v, w) = (j / 80, (j % 80) / 10, j % 10);
+        (p * 1000000 + u * 10000 + q * 1000 + v * 100 + r * 10 + w) as u64
+    }
+
+    // Correction grid on the 1km mesh, dB and dL in arc second, dH in metre
+    pub struct MeshGrid {
+        table: HashMap<u64, (f64, f64, f64)>,
+    }
+
+    impl MeshGrid {
+        pub fn open(path: &Path) -> io::Result<MeshGrid> {
+            MeshGrid::parse(BufReader::new(File::open(path)?))
+        }
+
+        pub fn parse<R: BufRead>(reader: R) -> io::Result<MeshGrid> {
+            let mut table = HashMap::new();
+            for line in reader.lines() {
+                let line = line?;
+                let v: Vec<&str> = line.split_whitespace().collect();
+                if v.len() < 3 || v[0].len() != 8 {
+                    // Header or unknown lines
+                    continue;
+                }
+                let code = match v[0].parse::<u64>() {
+                    Ok(x) => { x }
+                    Err(_) => { continue; }
+                };
+                let db = v[1].parse::<f64>();
+                let dl = v[2].parse::<f64>();
+                let dh = if v.len() > 3 { v[3].parse::<f64>().unwrap_or(0f64) } else { 0f64 };
+                match (db, dl) {
+                    (Ok(db), Ok(dl)) => { table.insert(code, (db, dl, dh)); }
+                    _ => { return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Invalid grid line: {line}"))); }
+                }
+            }
+            Ok(MeshGrid{table})
+        }
+
+        pub fn len(&self) -> usize {
+            self.table.len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.table.is_empty()
+        }
+
+        // Bilinear interpolation of the correction, None when any corner of the mesh is not in the grid
+        pub fn correction(&self, l: &LatLon) -> Option<(f64, f64, f64)> {
+            let (i, j) = mesh_index(l);
+            let t = l.lat * 120f64 - i as f64;
+            let s = (l.lon - 100f64) * 80f64 - j as f64;
+            let sw = self.table.get(&mesh_code(i, j))?;
+            let se = self.table.get(&mesh_code(i, j + 1))?;
+            let nw = self.table.get(&mesh_code(i + 1, j))?;
+            let ne = self.table.get(&mesh_code(i + 1, j + 1))?;
+            let f = |a: f64, b: f64, c: f64, d: f64| {
+                (1f64 - t) * (1f64 - s) * a + (1f64 - t) * s * b + t * (1f64 - s) * c + t * s * d
+            };
+            Some((f(sw.0, se.0, nw.0, ne.0), f(sw.1, se.1, nw.1, ne.1), f(sw.2, se.2, nw.2, ne.2)))
+        }
+
+        pub fn forward(&self, l: &LatLon) -> Option<LatLon> {
+            let (db, dl, _) = self.correction(l)?;
+            Some(LatLon::new(l.lat + db / 3600f64, l.lon + dl / 3600f64))
+        }
+
+        // The grid is defined on the source datum, so solve the backward conversion iteratively
+        pub fn backward(&self, l: &LatLon) -> Option<LatLon> {
+            let mut p = *l;
+            for _ in 0..4 {
+                let (db, dl, _) = self.correction(&p)?;
+                p = LatLon::new(l.lat - db / 3600f64, l.lon - dl / 3600f64);
+            }
+            Some(p)
+        }
+    }
+
+    pub struct DatumTransform {
+        tky2jgd: Option<MeshGrid>,
+        patch_jgd: Option<MeshGrid>,
+    }
+
+    impl DatumTransform {
+        pub fn new() -> DatumTransform {
+            Self {tky2jgd: None, patch_jgd: None}
+        }
+
+        // TKY2JGD.par from GSI
+        pub fn set_tky2jgd(self: &mut Self, path: &Path) -> io::Result<()> {
+            self.tky2jgd = Some(MeshGrid::open(path)?);
+            Ok(())
+        }
+
+        // PatchJGD parameter file (touhokutaiheiyouoki2011.par etc.) from GSI
+        pub fn set_patch_jgd(self: &mut Self, path: &Path) -> io::Result<()> {
+            self.patch_jgd = Some(MeshGrid::open(path)?);
+            Ok(())
+        }
+
+        pub fn transform(&self, l: &LatLon, from: Datum, to: Datum) -> LatLon {
+            if from == to {
+                return *l;
+            }
+            let jgd2000 = match from {
+                Datum::Tokyo => { self.tokyo_to_jgd2000(l) }
+                Datum::Jgd2000 => { *l }
+                Datum::Jgd2011 | Datum::Wgs84 => { self.jgd2011_to_jgd2000(l) }
+            };
+            match to {
+                Datum::Tokyo => { self.jgd2000_to_tokyo(&jgd2000) }
+                Datum::Jgd2000 => { jgd2000 }
+                Datum::Jgd2011 | Datum::Wgs84 => { self.jgd2000_to_jgd2011(&jgd2000) }
+            }
+        }
+
+        fn tokyo_to_jgd2000(&self, l: &LatLon) -> LatLon {
+            if let Some(x) = self.tky2jgd.as_ref().and_then(|g| g.forward(l)) {
+                return x;
+            }
+            Helmert::TOKYO_JGD2000.transform(l, 0f64, &Ellipsoid::BESSEL, &Ellipsoid::GRS80).0
+        }
+
+        fn jgd2000_to_tokyo(&self, l: &LatLon) -> LatLon {
+            if let Some(x) = self.tky2jgd.as_ref().and_then(|g| g.backward(l)) {
+                return x;
+            }
+            Helmert::TOKYO_JGD2000.inverse().transform(l, 0f64, &Ellipsoid::GRS80, &Ellipsoid::BESSEL).0
+        }
+
+        fn jgd2000_to_jgd2011(&self, l: &LatLon) -> LatLon {
+            self.patch_jgd.as_ref().and_then(|g| g.forward(l)).unwrap_or(*l)
+        }
+
+        fn jgd2011_to_jgd2000(&self, l: &LatLon) -> LatLon {
+            self.patch_jgd.as_ref().and_then(|g| g.backward(l)).unwrap_or(*l)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn dms(d: f64, m: f64, s: f64) -> f64 {
+            d + m / 60f64 + s / 3600f64
+        }
+
+        // The Japanese origin of longitude and latitude (日本経緯度原点) in both datums
+        #[test]
+        fn helmert_origin() {
+            let tokyo = LatLon::new(dms(35f64, 39f64, 17.5148), dms(139f64, 44f64, 40.5020));
+            let jgd = LatLon::new(dms(35f64, 39f64, 29.1572), dms(139f64, 44f64, 28.8759));
+            let t = DatumTransform::new();
+            let p = t.transform(&tokyo, Datum::Tokyo, Datum::Jgd2000);
+            assert!(p.geodesic_distance(&jgd) < 5f64);
+            let r = t.transform(&p, Datum::Jgd2000, Datum::Tokyo);
+            assert!(r.geodesic_distance(&tokyo) < 0.01);
+        }
+
+        #[test]
+        fn grid_interpolation() {
+            // Mesh 53394611 has the south west corner at 35.675N, 139.7625E
+            let par = "JGD2000-TokyoDatum Ver.2.1.1\nMeshCode dB(sec) dL(sec)\n\
+                53394611 11.0 -12.0\n53394612 11.2 -12.0\n53394621 11.0 -12.4\n53394622 11.2 -12.4\n";
+            let g = MeshGrid::parse(par.as_bytes()).unwrap();
+            assert_eq!(g.len(), 4);
+            let l = LatLon::new(35.675 + 0.5 / 120f64, 139.7625 + 0.5 / 80f64);
+            let (db, dl, _) = g.correction(&l).unwrap();
+            assert!((db - 11.1).abs() < 1e-9 && (dl + 12.2).abs() < 1e-9);
+            let p = g.forward(&l).unwrap();
+            let r = g.backward(&p).unwrap();
+            assert!((r.lat - l.lat).abs() < 1e-10 && (r.lon - l.lon).abs() < 1e-10);
+            assert!(g.correction(&LatLon::new(35.675 + 1.5 / 120f64, 139.7625)).is_none());
+        }
+    }
+}
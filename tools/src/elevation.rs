@@ -0,0 +1,260 @@
+//
+// Elevation gain / loss tools
+//
+// -- Method --
+// [Raw] sum of every altitude difference, overstates with barometric and GPS noise
+// [Hysteresis] count a change only after the altitude moved the threshold (m) from the last turning point
+//              3m ~ 5m suits barometric altimeters, 10m ~ 15m GNSS altitudes
+// [Smoothing] moving average over the window (m along the track), then sum the differences
+// [Dem] replace the altitudes with a digital elevation model, then apply the hysteresis threshold (m)
+//
+// -- DEM --
+// [DemTiles] GSI DEM text tiles (dem5a: z15, dem10b: z14) stored locally as {dir}/{z}/{x}/{y}.txt
+//            256 lines of 256 comma separated values, "e" for no data
+//
+pub mod elevation {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::PathBuf;
+    use crate::geometry::geometry_core::{LatLon, PixelCoord, PixelScale};
+    use crate::gpx::gpx::{Track, TrackRoute, TrackSegment};
+
+    pub trait ElevationModel {
+        // Elevation (m) of the location, None when the model has no data
+        fn elevation(&self, l: &LatLon) -> Option<f64>;
+    }
+
+    impl<F: Fn(&LatLon) -> Option<f64>> ElevationModel for F {
+        fn elevation(&self, l: &LatLon) -> Option<f64> {
+            self(l)
+        }
+    }
+
+    #[derive(Clone, Copy)]
+    pub enum GainMethod<'a> {
+        Raw,
+        Hysteresis(f64),
+        Smoothing(f64),
+        Dem(&'a dyn ElevationModel, f64),
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct ElevationChange {
+        pub gain: f64,      // m
+        pub loss: f64,      // m, positive
+    }
+
+    impl ElevationChange {
+        pub fn new() -> ElevationChange {
+            Self {gain: 0f64, loss: 0f64}
+        }
+
+        pub fn add(self: &mut Self, c: &ElevationChange) {
+            self.gain += c.gain;
+            self.loss += c.loss;
+        }
+    }
+
+    impl TrackSegment {
+        // Altitudes averaged over the window (m along the track), NaN stays NaN
+        pub fn smoothed_altitudes(&self, window: f64) -> Vec<f64> {
+            let altitudes: Vec<f64> = self.points.iter().map(|p| p.point.altitude).collect();
+            let mut along = Vec::with_capacity(self.points.len());
+            let mut d = 0f64;
+            for p in &self.points {
+                d += p.distance();
+                along.push(d);
+            }
+            smooth(&altitudes, &along, window)
+        }
+
+        pub fn elevation_change(&self, method: GainMethod) -> ElevationChange {
+            let altitudes: Vec<f64> = self.points.iter().map(|p| p.point.altitude).collect();
+            match method {
+                GainMethod::Raw => { hysteresis(&altitudes, 0f64) }
+                GainMethod::Hysteresis(threshold) => { hysteresis(&altitudes, threshold) }
+                GainMethod::Smoothing(window) => { hysteresis(&self.smoothed_altitudes(window), 0f64) }
+                GainMethod::Dem(model, threshold) => {
+                    // Keep the recorded altitude where the model has no data
+                    let corrected: Vec<f64> = self.points.iter()
+                        .map(|p| model.elevation(&p.point.location).unwrap_or(p.point.altitude))
+                        .collect();
+                    hysteresis(&corrected, threshold)
+                }
+            }
+        }
+    }
+
+    impl TrackRoute {
+        pub fn elevation_change(&self, method: GainMethod) -> ElevationChange {
+            let mut c = ElevationChange::new();
+            for s in &self.segments {
+                c.add(&s.elevation_change(method));
+            }
+            c
+        }
+    }
+
+    impl Track {
+        pub fn elevation_change(&self, method: GainMethod) -> ElevationChange {
+            let mut c = ElevationChange::new();
+            for r in &self.routes {
+                c.add(&r.elevation_change(method));
+            }
+            c
+        }
+    }
+
+    // NaN altitudes are skipped, threshold 0 sums every difference
+    fn hysteresis(altitudes: &[f64], threshold: f64) -> ElevationChange {
+        let mut c = ElevationChange::new();
+        let mut reference = f64::NAN;
+        for h in altitudes.iter().filter(|h| !h.is_nan()) {
+            if reference.is_nan() {
+                reference = *h;
+            } else if *h - reference >= threshold.max(f64::MIN_POSITIVE) {
+                c.gain += *h - reference;
+                reference = *h;
+            } else if reference - *h >= threshold.max(f64::MIN_POSITIVE) {
+                c.loss += reference - *h;
+                reference = *h;
+            }
+        }
+        c
+    }
+
+    // Centered moving average of the known altitudes within window / 2 along the track
+    fn smooth(altitudes: &[f64], along: &[f64], window: f64) -> Vec<f64> {
+        let half = window / 2f64;
+        let mut result = Vec::with_capacity(altitudes.len());
+        let (mut lo, mut hi) = (0, 0);
+        let (mut sum, mut n) = (0f64, 0);
+        for i in 0..altitudes.len() {
+            while hi < altitudes.len() && along[hi] <= along[i] + half {
+                if !altitudes[hi].is_nan() {
+                    sum += altitudes[hi];
+                    n += 1;
+                }
+                hi += 1;
+            }
+            while along[lo] < along[i] - half {
+                if !altitudes[lo].is_nan() {
+                    sum -= altitudes[lo];
+                    n -= 1;
+                }
+                lo += 1;
+            }
+            result.push(if altitudes[i].is_nan() || n == 0 { f64::NAN } else { sum / n as f64 });
+        }
+        result
+    }
+
+    // Heights of a tile by its x and y, None when the file is missing
+    type Tiles = HashMap<(i64, i64), Option<Vec<f64>>>;
+
+    // GSI DEM text tiles in the local directory, loaded on demand
+    pub struct DemTiles {
+        dir: PathBuf,
+        zoom: u32,
+        tiles: RefCell<Tiles>,
+    }
+
+    impl DemTiles {
+        pub fn new(dir: PathBuf, zoom: u32) -> DemTiles {
+            Self {dir, zoom, tiles: RefCell::new(HashMap::new())}
+        }
+
+        pub fn parse(text: &str) -> Option<Vec<f64>> {
+            let mut values = Vec::with_capacity(256 * 256);
+            for line in text.lines().filter(|l| !l.trim().is_empty()) {
+                let row: Vec<f64> = line.split(',').map(|v| v.trim().parse::<f64>().unwrap_or(f64::NAN)).collect();
+                if row.len() != 256 {
+                    return None;
+                }
+                values.extend(row);
+            }
+            if values.len() == 256 * 256 { Some(values) } else { None }
+        }
+
+        // Value of the pixel in the world pixel coordinate
+        fn pixel(&self, x: i64, y: i64) -> f64 {
+            let (tx, ty) = (x.div_euclid(256), y.div_euclid(256));
+            let mut tiles = self.tiles.borrow_mut();
+            let tile = tiles.entry((tx, ty)).or_insert_with(|| {
+                let path = self.dir.join(format!("{}/{}/{}.txt", self.zoom, tx, ty));
+                fs::read_to_string(path).ok().and_then(|t| DemTiles::parse(&t))
+            });
+            match tile {
+                Some(values) => { values[(y.rem_euclid(256) * 256 + x.rem_euclid(256)) as usize] }
+                None => { f64::NAN }
+            }
+        }
+    }
+
+    impl ElevationModel for DemTiles {
+        // Bilinear interpolation between the pixel centres
+        fn elevation(&self, l: &LatLon) -> Option<f64> {
+            let p = PixelCoord::from_latlon(l, self.zoom, PixelScale::STANDARD);
+            let (x, y) = (p.x - 0.5, p.y - 0.5);
+            let (i, j) = (x.floor() as i64, y.floor() as i64);
+            let (s, t) = (x - i as f64, y - j as f64);
+            let h = (1f64 - s) * (1f64 - t) * self.pixel(i, j) + s * (1f64 - t) * self.pixel(i + 1, j)
+                + (1f64 - s) * t * self.pixel(i, j + 1) + s * t * self.pixel(i + 1, j + 1);
+            if h.is_nan() { None } else { Some(h) }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::ffi::OsString;
+        use crate::gpx::gpx::TrackPoint;
+        use crate::gpxperser::gpx_parser::GPXParser;
+
+        // 100m climb over 1km with +-2m noise on every point
+        fn noisy_climb() -> TrackSegment {
+            let mut segment = TrackSegment::new();
+            for i in 0..=100 {
+                let mut p = TrackPoint::new(35.0 + i as f64 * 0.00009, 139.0);
+                p.altitude = 1000f64 + i as f64 + if i % 2 == 0 { 2f64 } else { -2f64 };
+                segment.add_point(p);
+            }
+            segment
+        }
+
+        #[test]
+        fn gain_and_loss() {
+            let segment = noisy_climb();
+            let raw = segment.elevation_change(GainMethod::Raw);
+            assert!(raw.gain > 240f64);
+            assert!((raw.gain - raw.loss - 100f64).abs() < 1e-6);
+            let h = segment.elevation_change(GainMethod::Hysteresis(5f64));
+            assert!((h.gain - 100f64).abs() <= 5f64 && h.loss == 0f64);
+            let s = segment.elevation_change(GainMethod::Smoothing(50f64));
+            assert!((s.gain - 100f64).abs() <= 5f64 && s.loss < 1f64);
+            let dem = |l: &LatLon| Some(1000f64 + (l.lat - 35.0) / 0.00009);
+            let d = segment.elevation_change(GainMethod::Dem(&dem, 1f64));
+            assert!((d.gain - 100f64).abs() < 1f64 && d.loss == 0f64);
+        }
+
+        #[test]
+        fn samples() {
+            // Filtered totals never exceed the naive sum
+            for name in ["Garmin", "RunKeeper", "Suunto", "YamakeiOnline", "Yamap"] {
+                let track = GPXParser::new(&OsString::from(format!("../samples/gpx/{name}.gpx"))).unwrap().open().unwrap();
+                let raw = track.elevation_change(GainMethod::Raw);
+                for method in [GainMethod::Hysteresis(5f64), GainMethod::Smoothing(100f64)] {
+                    let c = track.elevation_change(method);
+                    assert!(c.gain <= raw.gain && c.loss <= raw.loss, "{name}");
+                    // The barometer of Garmin.gpx steps up and down, the filters take off more than 10%
+                    // but keep the net change
+                    if name == "Garmin" {
+                        assert!(c.gain < raw.gain * 0.9 && c.loss < raw.loss * 0.9);
+                        assert!(((c.gain - c.loss) - (raw.gain - raw.loss)).abs() < 10f64);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,259 @@
+
+//
+// Geodesic calculate tools
+//
+// Solves the geodesic problems on an ellipsoid of revolution with Vincenty's formulae.
+//   Inverse: two locations -> distance, initial bearing, final bearing
+//   Direct: location, bearing and distance -> destination, final bearing
+// Bearings are in degrees clockwise from north (0.0 ~ 360.0), distances in metres.
+//
+pub mod geodesic {
+    use std::f64::consts::PI;
+    use crate::geometry::geometry_core::LatLon;
+
+    const MAX_ITERATION: usize = 200;
+    const EPSILON: f64 = 1e-12;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct Ellipsoid {
+        pub a: f64,     // Semi-major axis (m)
+        pub f: f64,     // Flattening
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    pub struct Geodesic {
+        pub distance: f64,
+        pub initial_bearing: f64,
+        pub final_bearing: f64,
+        // false when Vincenty did not converge (nearly antipodal) and the spherical fallback was used
+        pub converged: bool,
+    }
+
+    impl Ellipsoid {
+        pub const WGS84: Ellipsoid = Ellipsoid{a: 6378137f64, f: 1f64 / 298.257223563};
+        pub const GRS80: Ellipsoid = Ellipsoid{a: 6378137f64, f: 1f64 / 298.257222101};
+        pub const BESSEL: Ellipsoid = Ellipsoid{a: 6377397.155, f: 1f64 / 299.152813};
+
+        pub fn b(&self) -> f64 {
+            self.a * (1f64 - self.f)
+        }
+
+        // First eccentricity squared
+        pub fn e2(&self) -> f64 {
+            self.f * (2f64 - self.f)
+        }
+
+        // Mean radius (2a + b) / 3
+        pub fn mean_radius(&self) -> f64 {
+            (2f64 * self.a + self.b()) / 3f64
+        }
+
+        pub fn inverse(&self, from: &LatLon, to: &LatLon) -> Geodesic {
+            let b = self.b();
+            let f = self.f;
+            let (phi1, lambda1) = from.radian();
+            let (phi2, lambda2) = to.radian();
+            let l = normalize_radian(lambda2 - lambda1);
+            let u1 = ((1f64 - f) * phi1.tan()).atan();
+            let u2 = ((1f64 - f) * phi2.tan()).atan();
+            let (sin_u1, cos_u1) = u1.sin_cos();
+            let (sin_u2, cos_u2) = u2.sin_cos();
+
+            let mut lambda = l;
+            let mut converged = false;
+            let mut sin_sigma = 0f64;
+            let mut cos_sigma = 1f64;
+            let mut sigma = 0f64;
+            let mut cos2_alpha = 1f64;
+            let mut cos_2sigma_m = 0f64;
+            let mut sin_lambda = 0f64;
+            let mut cos_lambda = 1f64;
+
+            for _ in 0..MAX_ITERATION {
+                (sin_lambda, cos_lambda) = lambda.sin_cos();
+                let t1 = cos_u2 * sin_lambda;
+                let t2 = cos_u1 * sin_u2 - sin_u1 * cos_u2 * cos_lambda;
+                sin_sigma = (t1 * t1 + t2 * t2).sqrt();
+                if sin_sigma == 0f64 {
+                    // Coincident points
+                    return Geodesic{distance: 0f64, initial_bearing: 0f64, final_bearing: 0f64, converged: true};
+                }
+                cos_sigma = sin_u1 * sin_u2 + cos_u1 * cos_u2 * cos_lambda;
+                sigma = sin_sigma.atan2(cos_sigma);
+                let sin_alpha = cos_u1 * cos_u2 * sin_lambda / sin_sigma;
+                cos2_alpha = 1f64 - sin_alpha * sin_alpha;
+                // Both points on the equator, cos2_alpha == 0
+                cos_2sigma_m = if cos2_alpha != 0f64 { cos_sigma - 2f64 * sin_u1 * sin_u2 / cos2_alpha } else { 0f64 };
+                let c = f / 16f64 * cos2_alpha * (4f64 + f * (4f64 - 3f64 * cos2_alpha));
+                let prev = lambda;
+                lambda = l + (1f64 - c) * f * sin_alpha
+                    * (sigma + c * sin_sigma * (cos_2sigma_m + c * cos_sigma * (-1f64 + 2f64 * cos_2sigma_m * cos_2sigma_m)));
+                if lambda.abs() > PI {
+                    break;
+                }
+                if (lambda - prev).abs() < EPSILON {
+                    converged = true;
+                    break;
+                }
+            }
+
+            if !converged {
+                return self.spherical_fallback(from, to);
+            }
+
+            let u_sq = cos2_alpha * (self.a * self.a - b * b) / (b * b);
+            let (a_coef, b_coef) = series_coefficients(u_sq);
+            let delta_sigma = delta_sigma(b_coef, sin_sigma, cos_sigma, cos_2sigma_m);
+            let distance = b * a_coef * (sigma - delta_sigma);
+
+            let alpha1 = (cos_u2 * sin_lambda).atan2(cos_u1 * sin_u2 - sin_u1 * cos_u2 * cos_lambda);
+            let alpha2 = (cos_u1 * sin_lambda).atan2(-sin_u1 * cos_u2 + cos_u1 * sin_u2 * cos_lambda);
+
+            Geodesic{
+                distance,
+                initial_bearing: normalize_bearing(alpha1.to_degrees()),
+                final_bearing: normalize_bearing(alpha2.to_degrees()),
+                converged: true,
+            }
+        }
+
+        // Returns the destination and the final bearing at the destination
+        pub fn direct(&self, from: &LatLon, bearing: f64, distance: f64) -> (LatLon, f64) {
+            let b = self.b();
+            let f = self.f;
+            let (phi1, lambda1) = from.radian();
+            let alpha1 = bearing.to_radians();
+            let (sin_alpha1, cos_alpha1) = alpha1.sin_cos();
+
+            let tan_u1 = (1f64 - f) * phi1.tan();
+            let cos_u1 = 1f64 / (1f64 + tan_u1 * tan_u1).sqrt();
+            let sin_u1 = tan_u1 * cos_u1;
+            let sigma1 = tan_u1.atan2(cos_alpha1);
+            let sin_alpha = cos_u1 * sin_alpha1;
+            let cos2_alpha = 1f64 - sin_alpha * sin_alpha;
+            let u_sq = cos2_alpha * (self.a * self.a - b * b) / (b * b);
+            let (a_coef, b_coef) = series_coefficients(u_sq);
+
+            let mut sigma = distance / (b * a_coef);
+            let mut cos_2sigma_m = (2f64 * sigma1 + sigma).cos();
+            for _ in 0..MAX_ITERATION {
+                cos_2sigma_m = (2f64 * sigma1 + sigma).cos();
+                let (sin_sigma, cos_sigma) = sigma.sin_cos();
+                let prev = sigma;
+                sigma = distance / (b * a_coef) + delta_sigma(b_coef, sin_sigma, cos_sigma, cos_2sigma_m);
+                if (sigma - prev).abs() < EPSILON {
+                    break;
+                }
+            }
+            let (sin_sigma, cos_sigma) = sigma.sin_cos();
+
+            let t = sin_u1 * sin_sigma - cos_u1 * cos_sigma * cos_alpha1;
+            let phi2 = (sin_u1 * cos_sigma + cos_u1 * sin_sigma * cos_alpha1)
+                .atan2((1f64 - f) * (sin_alpha * sin_alpha + t * t).sqrt());
+            let lambda = (sin_sigma * sin_alpha1).atan2(cos_u1 * cos_sigma - sin_u1 * sin_sigma * cos_alpha1);
+            let c = f / 16f64 * cos2_alpha * (4f64 + f * (4f64 - 3f64 * cos2_alpha));
+            let l = lambda - (1f64 - c) * f * sin_alpha
+                * (sigma + c * sin_sigma * (cos_2sigma_m + c * cos_sigma * (-1f64 + 2f64 * cos_2sigma_m * cos_2sigma_m)));
+            let lambda2 = normalize_radian(lambda1 + l);
+            let alpha2 = sin_alpha.atan2(-t);
+
+            (LatLon::new(phi2.to_degrees(), lambda2.to_degrees()), normalize_bearing(alpha2.to_degrees()))
+        }
+
+        // Great circle on a sphere of the mean radius, used when Vincenty does not converge
+        fn spherical_fallback(&self, from: &LatLon, to: &LatLon) -> Geodesic {
+            let (phi1, lambda1) = from.radian();
+            let (phi2, lambda2) = to.radian();
+            let dl = lambda2 - lambda1;
+            let s = ((phi2 - phi1) / 2f64).sin().powi(2)
+                + phi1.cos() * phi2.cos() * (dl / 2f64).sin().powi(2);
+            let sigma = 2f64 * s.sqrt().min(1f64).asin();
+            let alpha1 = (dl.sin() * phi2.cos()).atan2(phi1.cos() * phi2.sin() - phi1.sin() * phi2.cos() * dl.cos());
+            let alpha2 = (dl.sin() * phi1.cos()).atan2(-phi2.cos() * phi1.sin() + phi2.sin() * phi1.cos() * dl.cos());
+            Geodesic{
+                distance: sigma * self.mean_radius(),
+                initial_bearing: normalize_bearing(alpha1.to_degrees()),
+                final_bearing: normalize_bearing(alpha2.to_degrees()),
+                converged: false,
+            }
+        }
+    }
+
+    fn series_coefficients(u_sq: f64) -> (f64, f64) {
+        let a = 1f64 + u_sq / 16384f64 * (4096f64 + u_sq * (-768f64 + u_sq * (320f64 - 175f64 * u_sq)));
+        let b = u_sq / 1024f64 * (256f64 + u_sq * (-128f64 + u_sq * (74f64 - 47f64 * u_sq)));
+        (a, b)
+    }
+
+    fn delta_sigma(b: f64, sin_sigma: f64, cos_sigma: f64, cos_2sigma_m: f64) -> f64 {
+        let c2 = cos_2sigma_m * cos_2sigma_m;
+        b * sin_sigma * (cos_2sigma_m + b / 4f64 * (cos_sigma * (-1f64 + 2f64 * c2)
+            - b / 6f64 * cos_2sigma_m * (-3f64 + 4f64 * sin_sigma * sin_sigma) * (-3f64 + 4f64 * c2)))
+    }
+
+    // -PI ~ +PI
+    fn normalize_radian(r: f64) -> f64 {
+        let r = (r + PI) % (2f64 * PI);
+        if r < 0f64 { r + PI } else { r - PI }
+    }
+
+    // 0.0 ~ 360.0
+    pub fn normalize_bearing(d: f64) -> f64 {
+        let d = d % 360f64;
+        if d < 0f64 { d + 360f64 } else { d }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn dms(d: f64, m: f64, s: f64) -> f64 {
+            d.signum() * (d.abs() + m / 60f64 + s / 3600f64)
+        }
+
+        // Flinders Peak -> Buninyong, the worked example published by Geoscience Australia (GRS80)
+        fn flinders_peak() -> LatLon {
+            LatLon::new(-dms(37f64, 57f64, 3.72030), dms(144f64, 25f64, 29.52440))
+        }
+
+        fn buninyong() -> LatLon {
+            LatLon::new(-dms(37f64, 39f64, 10.15610), dms(143f64, 55f64, 35.38390))
+        }
+
+        #[test]
+        fn inverse_flinders_peak_buninyong() {
+            let g = Ellipsoid::GRS80.inverse(&flinders_peak(), &buninyong());
+            assert!(g.converged);
+            assert!((g.distance - 54972.271).abs() < 1e-3);
+            assert!((g.initial_bearing - dms(306f64, 52f64, 5.37)).abs() < 1f64 / 3600f64);
+            assert!((g.final_bearing - dms(307f64, 10f64, 25.07)).abs() < 1f64 / 3600f64);
+        }
+
+        #[test]
+        fn direct_flinders_peak_buninyong() {
+            let (p, final_bearing) = Ellipsoid::GRS80.direct(&flinders_peak(), dms(306f64, 52f64, 5.37), 54972.271);
+            let expected = buninyong();
+            assert!((p.lat - expected.lat).abs() < 1e-5 / 3600f64 * 100f64);
+            assert!((p.lon - expected.lon).abs() < 1e-5 / 3600f64 * 100f64);
+            assert!((final_bearing - dms(307f64, 10f64, 25.07)).abs() < 1f64 / 3600f64);
+        }
+
+        #[test]
+        fn inverse_along_equator_and_meridian() {
+            // A quarter of the equator is a * PI / 2
+            let g = Ellipsoid::WGS84.inverse(&LatLon::new(0f64, 0f64), &LatLon::new(0f64, 90f64));
+            assert!((g.distance - 10018754.171394622).abs() < 1e-3);
+            assert!((g.initial_bearing - 90f64).abs() < 1e-9);
+            // Equator to pole is the WGS84 quarter meridian
+            let g = Ellipsoid::WGS84.inverse(&LatLon::new(0f64, 0f64), &LatLon::new(90f64, 0f64));
+            assert!((g.distance - 10001965.729).abs() < 1e-3);
+        }
+
+        #[test]
+        fn inverse_antipodal_falls_back() {
+            let g = Ellipsoid::WGS84.inverse(&LatLon::new(0f64, 0f64), &LatLon::new(0.5, 179.7));
+            assert!(g.distance > 19900000f64 && g.distance < 20020000f64);
+            assert!(!g.converged);
+        }
+    }
+}
@@ -0,0 +1,181 @@
+//
+// Geoid tools
+//
+// -- Vertical datum --
+// [Ellipsoidal] height above the WGS84 (GRS80) ellipsoid, as GNSS receivers measure
+// [Orthometric] height above the geoid (mean sea level), as GSI maps show
+//   orthometric = ellipsoidal - undulation
+//
+// Grid files are supplied locally:
+// [GSI] 日本のジオイド2011 (gsigeo2011_ver2.asc)
+//   header: south(lat) west(lon) dlat dlon rows columns ikind version
+//   data: rows from south to north, each row west to east, 999.0000 for no data
+// [GRD] EGM96 / EGM2008 ASCII grid (WW15MGH.GRD style)
+//   header: south north west east dlat dlon
+//   data: rows from north to south, each row west to east
+//
+pub mod geoid {
+    use std::fs::File;
+    use std::io;
+    use std::io::{BufRead, BufReader};
+    use std::path::Path;
+    use crate::geometry::geometry_core::LatLon;
+
+    const NO_DATA: f64 = 999f64;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum VerticalDatum {
+        Unknown,
+        Ellipsoidal,
+        Orthometric,
+    }
+
+    impl VerticalDatum {
+        pub fn as_str(&self) -> &str {
+            match self {
+                VerticalDatum::Unknown => { "Unknown" }
+                VerticalDatum::Ellipsoidal => { "Ellipsoidal" }
+                VerticalDatum::Orthometric => { "Orthometric" }
+            }
+        }
+    }
+
+    // Regular grid of the geoid undulation (m), rows from south to north
+    pub struct GeoidGrid {
+        south: f64,
+        west: f64,
+        dlat: f64,
+        dlon: f64,
+        rows: usize,
+        columns: usize,
+        values: Vec<f64>,
+    }
+
+    impl GeoidGrid {
+        pub fn open(path: &Path) -> io::Result<GeoidGrid> {
+            GeoidGrid::parse(BufReader::new(File::open(path)?))
+        }
+
+        // The format is told from the header line
+        pub fn parse<R: BufRead>(reader: R) -> io::Result<GeoidGrid> {
+            let mut lines = reader.lines();
+            let header = match lines.next() {
+                Some(line) => { line? }
+                None => { return Err(invalid("Empty geoid grid")); }
+            };
+            let h: Vec<&str> = header.split_whitespace().collect();
+            let n: Vec<f64> = h.iter().take(6).map_while(|x| x.parse::<f64>().ok()).collect();
+            if n.len() < 6 {
+                return Err(invalid(&format!("Invalid geoid grid header: {header}")));
+            }
+            let mut values = Vec::new();
+            for line in lines {
+                for v in line?.split_whitespace() {
+                    values.push(v.parse::<f64>().map_err(|_| invalid(&format!("Invalid geoid value: {v}")))?);
+                }
+            }
+
+            let mut grid = if h.len() >= 8 {
+                // GSI
+                GeoidGrid{south: n[0], west: n[1], dlat: n[2], dlon: n[3],
+                    rows: n[4] as usize, columns: n[5] as usize, values}
+            } else {
+                // GRD, turn the rows to start from south
+                let rows = ((n[1] - n[0]) / n[4]).round() as usize + 1;
+                let columns = ((n[3] - n[2]) / n[5]).round() as usize + 1;
+                let mut flipped = Vec::with_capacity(values.len());
+                for row in values.chunks(columns).rev() {
+                    flipped.extend_from_slice(row);
+                }
+                GeoidGrid{south: n[0], west: n[2], dlat: n[4], dlon: n[5], rows, columns, values: flipped}
+            };
+            if grid.rows < 2 || grid.columns < 2 || grid.values.len() != grid.rows * grid.columns
+                || grid.dlat <= 0f64 || grid.dlon <= 0f64 {
+                return Err(invalid("Geoid grid size does not match the header"));
+            }
+            for v in grid.values.iter_mut() {
+                if *v >= NO_DATA {
+                    *v = f64::NAN;
+                }
+            }
+            Ok(grid)
+        }
+
+        // Whole longitude circle is in the grid, the east edge joins the west edge
+        fn is_global(&self) -> bool {
+            self.columns as f64 * self.dlon >= 360f64 - 1e-9
+        }
+
+        // Bilinear interpolation of the undulation (m), None outside of the grid or next to no data
+        pub fn undulation(&self, l: &LatLon) -> Option<f64> {
+            let y = (l.lat - self.south) / self.dlat;
+            let x = (l.lon - self.west).rem_euclid(360f64) / self.dlon;
+            let last_row = (self.rows - 1) as f64;
+            let last_column = (self.columns - 1) as f64;
+            if !(0f64..=last_row).contains(&y) || (x > last_column && !self.is_global()) {
+                return None;
+            }
+            let i = (y.floor() as usize).min(self.rows - 2);
+            let j = x.floor() as usize;
+            let t = y - i as f64;
+            let s = x - j as f64;
+            let j1 = if j + 1 < self.columns { j + 1 } else { (j + 1) % self.columns };
+            let j = j.min(self.columns - 1);
+            let v = |i: usize, j: usize| self.values[i * self.columns + j];
+            let u = (1f64 - t) * (1f64 - s) * v(i, j) + (1f64 - t) * s * v(i, j1)
+                + t * (1f64 - s) * v(i + 1, j) + t * s * v(i + 1, j1);
+            if u.is_nan() { None } else { Some(u) }
+        }
+
+        // Height in the other vertical datum, None when it can not be converted
+        pub fn convert(&self, l: &LatLon, height: f64, from: VerticalDatum, to: VerticalDatum) -> Option<f64> {
+            match (from, to) {
+                (VerticalDatum::Ellipsoidal, VerticalDatum::Orthometric) => { Some(height - self.undulation(l)?) }
+                (VerticalDatum::Orthometric, VerticalDatum::Ellipsoidal) => { Some(height + self.undulation(l)?) }
+                (f, t) if f == t && f != VerticalDatum::Unknown => { Some(height) }
+                _ => { None }
+            }
+        }
+    }
+
+    fn invalid(message: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn gsi_grid() {
+            // 3 x 3 grid around Tokyo, 1 minute by 1.5 minutes
+            let text = "35.00000 139.00000 0.016667 0.025000 3 3 1 ver2.1\n\
+                        36.0000 36.2000 36.4000\n\
+                        37.0000 37.2000 37.4000\n\
+                        38.0000 999.0000 38.4000\n";
+            let grid = GeoidGrid::parse(text.as_bytes()).unwrap();
+            assert!((grid.undulation(&LatLon::new(35.0, 139.0)).unwrap() - 36.0).abs() < 1e-9);
+            let u = grid.undulation(&LatLon::new(35.0083335, 139.0125)).unwrap();
+            assert!((u - 36.6).abs() < 1e-6);
+            // Next to no data, outside
+            assert!(grid.undulation(&LatLon::new(35.03, 139.01)).is_none());
+            assert!(grid.undulation(&LatLon::new(34.99, 139.01)).is_none());
+            let h = grid.convert(&LatLon::new(35.0, 139.0), 100f64, VerticalDatum::Ellipsoidal, VerticalDatum::Orthometric);
+            assert_eq!(h, Some(64f64));
+        }
+
+        #[test]
+        fn grd_grid() {
+            // Global 90 degree grid, rows from north
+            let text = "-90.0 90.0 0.0 270.0 90.0 90.0\n\
+                        10.0 10.0 10.0 10.0\n\
+                        0.0 20.0 40.0 60.0\n\
+                        -10.0 -10.0 -10.0 -10.0\n";
+            let grid = GeoidGrid::parse(text.as_bytes()).unwrap();
+            assert_eq!(grid.undulation(&LatLon::new(90.0, 0.0)), Some(10f64));
+            assert_eq!(grid.undulation(&LatLon::new(0.0, 45.0)), Some(10f64));
+            // Between 270 and 360 (= 0) degree east
+            assert_eq!(grid.undulation(&LatLon::new(0.0, -45.0)), Some(30f64));
+        }
+    }
+}
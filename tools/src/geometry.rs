@@ -6,13 +6,16 @@
 // [LatLon] <Whole> Lat: -90.0 ~ + 90.0 Lon: -180.0 ~ +180.0
 // [Coord] <Whole> v: 0.0 ~ 1.0 u: 0.0 ~ 1.0
 // [Tile] <Whole> tx: 0 ~ 2^z, tx: 0 ~ 2^z <Relative> x: 0.0 ~ 1.0, y: 0.0 ~ 1.0 <Scale> z: 0 ~ 23
-// [Pixel] <Whole> x: 0 ~ 2^z - 1, y: 0 ~ 2^z - 1
+// [Pixel] <Whole> x: 0.0 ~ size * 2^z, y: 0.0 ~ size * 2^z <Scale> size: tile size (256 or 512) * device pixel ratio
 //
 pub mod geometry_core {
+    use std::collections::BTreeSet;
     use std::f64::consts::PI;
     use std::fmt;
+    use std::ops::RangeInclusive;
     use num::complex::ComplexFloat;
     use num::integer::sqrt;
+    use crate::geodesic::geodesic::Ellipsoid;
 
     #[derive(Debug, Clone, Copy)]
     pub struct LatLon {
@@ -27,7 +30,7 @@ pub mod geometry_core {
         pub v: f64
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, Clone, Copy, PartialEq)]
     pub struct TileCoord {
         pub tx: i64,
         pub ty: i64,
@@ -36,6 +39,33 @@ pub mod geometry_core {
         pub z: u32
     }
 
+    // How a tile server addresses tiles
+    //   Xyz: y = 0 at north (OSM, GSI)
+    //   Tms: y = 0 at south
+    //   Quadkey: Bing style base 4 string, one digit per zoom level
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum TileScheme {
+        Xyz,
+        Tms,
+        Quadkey,
+    }
+
+    // Tile size in CSS pixel and the device pixel ratio
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct PixelScale {
+        pub tile_size: u32,
+        pub ratio: f64,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct PixelCoord {
+        pub x: f64,
+        pub y: f64,
+        pub z: u32,
+        pub scale: PixelScale,
+    }
+
+    // Bounding box, west > east means the box crosses the 180 degree meridian
     #[derive(Clone, Debug)]
     pub struct Area {
         pub north_west: LatLon,
@@ -50,31 +80,219 @@ pub mod geometry_core {
             }
         }
 
+        pub fn new(north: f64, west: f64, south: f64, east: f64) -> Area {
+            Self {
+                north_west: LatLon::new(north, west),
+                south_east: LatLon::new(south, east)
+            }
+        }
+
+        pub fn world() -> Area {
+            Area::new(90f64, -180f64, -90f64, 180f64)
+        }
+
+        pub fn is_valid(&self) -> bool {
+            self.north_west.lat >= self.south_east.lat
+        }
+
+        pub fn north(&self) -> f64 { self.north_west.lat }
+        pub fn south(&self) -> f64 { self.south_east.lat }
+        pub fn west(&self) -> f64 { self.north_west.lon }
+        pub fn east(&self) -> f64 { self.south_east.lon }
+
+        pub fn crosses_antimeridian(&self) -> bool {
+            self.is_valid() && (self.west() > self.east())
+        }
+
+        // (latitude span, longitude span) in degree
+        pub fn span(&self) -> (f64, f64) {
+            if !self.is_valid() {
+                return (0f64, 0f64);
+            }
+            (self.north() - self.south(), lon_span(self.west(), self.east()))
+        }
+
+        pub fn center(&self) -> LatLon {
+            let lon = self.west() + self.span().1 / 2f64;
+            LatLon::new((self.north() + self.south()) / 2f64, wrap_lon(lon))
+        }
+
+        // Extend the area to include the location, toward the side which keeps the area smaller
         pub fn enter(self: &mut Self, location: &LatLon) {
+            if !self.is_valid() {
+                *self = Area::new(location.lat, location.lon, location.lat, location.lon);
+                return;
+            }
             if (location.lat > self.north_west.lat) {self.north_west.lat = location.lat}
-            if (location.lon < self.north_west.lon) {self.north_west.lon = location.lon}
             if (location.lat < self.south_east.lat) {self.south_east.lat = location.lat}
-            if (location.lon > self.south_east.lon) {self.south_east.lon = location.lon}
+            if !lon_contains(self.west(), self.east(), location.lon) {
+                let to_west = lon_span(location.lon, self.west());
+                let to_east = lon_span(self.east(), location.lon);
+                if to_west < to_east {
+                    self.north_west.lon = location.lon;
+                } else {
+                    self.south_east.lon = location.lon;
+                }
+            }
         }
 
         pub fn add(self: &mut Self, area: &Area) {
-            if (area.north_west.lat > self.north_west.lat) {self.north_west.lat = area.north_west.lat}
-            if (area.north_west.lon < self.north_west.lon) {self.north_west.lon = area.north_west.lon}
-            if (area.south_east.lat < self.south_east.lat) {self.south_east.lat = area.south_east.lat}
-            if (area.south_east.lon > self.south_east.lon) {self.south_east.lon = area.south_east.lon}
+            *self = self.union(area);
+        }
+
+        // Inclusive, the edges are inside
+        pub fn contains(&self, location: &LatLon) -> bool {
+            self.is_valid()
+                && (location.lat <= self.north()) && (location.lat >= self.south())
+                && lon_contains(self.west(), self.east(), location.lon)
+        }
+
+        pub fn contains_area(&self, area: &Area) -> bool {
+            self.is_valid() && area.is_valid()
+                && (area.north() <= self.north()) && (area.south() >= self.south())
+                && lon_contains_interval(self.west(), self.east(), area.west(), area.east())
         }
 
+        // True if this area is inside of the area
         pub fn is_in(self: &Self, area: &Area) -> bool {
-            if ((area.north_west.lat > self.north_west.lat) && (area.south_east.lat < self.south_east.lat)
-                && (area.north_west.lon < self.north_west.lon) && (area.south_east.lon > self.south_east.lon)) {
-                return true;
+            area.contains_area(self)
+        }
+
+        pub fn intersects(&self, area: &Area) -> bool {
+            self.intersection(area).is_some()
+        }
+
+        pub fn intersection(&self, area: &Area) -> Option<Area> {
+            if !self.is_valid() || !area.is_valid() {
+                return None;
+            }
+            let north = self.north().min(area.north());
+            let south = self.south().max(area.south());
+            if north < south {
+                return None;
+            }
+            let (west, east) = lon_intersection(self.west(), self.east(), area.west(), area.east())?;
+            Some(Area::new(north, west, south, east))
+        }
+
+        pub fn union(&self, area: &Area) -> Area {
+            if !area.is_valid() {
+                return self.clone();
+            }
+            if !self.is_valid() {
+                return area.clone();
+            }
+            let (west, east) = lon_union(self.west(), self.east(), area.west(), area.east());
+            Area::new(self.north().max(area.north()), west, self.south().min(area.south()), east)
+        }
+
+        // Grow the area by the distance (m) to every direction
+        pub fn expand(&self, distance: f64) -> Area {
+            if !self.is_valid() {
+                return self.clone();
             }
+            let dlat = (distance / Ellipsoid::WGS84.mean_radius()).to_degrees();
+            let north = (self.north() + dlat).min(90f64);
+            let south = (self.south() - dlat).max(-90f64);
+            let lat = north.abs().max(south.abs());
+            if lat >= 90f64 {
+                return Area::new(north, -180f64, south, 180f64);
+            }
+            let dlon = dlat / lat.to_radians().cos();
+            if self.span().1 + 2f64 * dlon >= 360f64 {
+                return Area::new(north, -180f64, south, 180f64);
+            }
+            Area::new(north, wrap_lon(self.west() - dlon), south, wrap_lon(self.east() + dlon))
+        }
+    }
+
+    // -180.0 ~ +180.0
+    pub(crate) fn wrap_lon(lon: f64) -> f64 {
+        if (-180f64..=180f64).contains(&lon) {
+            return lon;
+        }
+        (lon + 540f64).rem_euclid(360f64) - 180f64
+    }
+
+    // Span of the longitude interval going east from west to east
+    fn lon_span(west: f64, east: f64) -> f64 {
+        if west <= east { east - west } else { east - west + 360f64 }
+    }
 
-            return false;
+    fn lon_contains(west: f64, east: f64, lon: f64) -> bool {
+        if west <= east {
+            (lon >= west) && (lon <= east)
+        } else {
+            (lon >= west) || (lon <= east)
         }
     }
 
+    fn lon_contains_interval(west: f64, east: f64, w: f64, e: f64) -> bool {
+        let span = lon_span(west, east);
+        if span >= 360f64 {
+            return true;
+        }
+        lon_contains(west, east, w) && (lon_span(west, w) + lon_span(w, e) <= span)
+    }
+
+    fn lon_intersection(w1: f64, e1: f64, w2: f64, e2: f64) -> Option<(f64, f64)> {
+        // Start from the west edge which is inside the other, end at the first east edge reached from there
+        let start = if lon_contains(w1, e1, w2) {
+            w2
+        } else if lon_contains(w2, e2, w1) {
+            w1
+        } else {
+            return None;
+        };
+        let end = if lon_span(start, e1) <= lon_span(start, e2) { e1 } else { e2 };
+        Some((start, end))
+    }
+
+    fn lon_union(w1: f64, e1: f64, w2: f64, e2: f64) -> (f64, f64) {
+        if lon_contains_interval(w1, e1, w2, e2) {
+            return (w1, e1);
+        }
+        if lon_contains_interval(w2, e2, w1, e1) {
+            return (w2, e2);
+        }
+        // Either from the west of first to the east of second, or the other way round
+        let a = (w1, e2);
+        let b = (w2, e1);
+        let mut best: Option<(f64, f64)> = None;
+        for c in [a, b] {
+            if lon_contains_interval(c.0, c.1, w1, e1) && lon_contains_interval(c.0, c.1, w2, e2) {
+                match best {
+                    Some(x) if lon_span(x.0, x.1) <= lon_span(c.0, c.1) => {}
+                    _ => { best = Some(c); }
+                }
+            }
+        }
+        best.unwrap_or((-180f64, 180f64))
+    }
+
     const RADIUS: f64 = 6378137f64; // Earth Radius
+    const MAX_LATITUDE: f64 = 85.05112877980659; // Edge of Web Mercator
+
+    // How horizontal distances between two locations are measured
+    //   Chord: straight line through the sphere of the equatorial radius
+    //   Sphere: great circle on the sphere of the WGS84 mean radius
+    //   Ellipsoid: geodesic on the WGS84 ellipsoid
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum DistanceModel {
+        Chord,
+        Sphere,
+        Ellipsoid,
+    }
+
+    impl DistanceModel {
+        pub fn distance(&self, a: &LatLon, b: &LatLon) -> f64 {
+            match self {
+                DistanceModel::Chord => { a.distance(b) }
+                DistanceModel::Sphere => { a.great_circle_distance(b) }
+                DistanceModel::Ellipsoid => { Ellipsoid::WGS84.inverse(a, b).distance }
+            }
+        }
+    }
 
     impl LatLon {
         pub fn new(lat: f64, lon: f64) -> LatLon{
@@ -83,12 +301,14 @@ pub mod geometry_core {
             LatLon{id: la * 0x100000000 + lo, lat: lat, lon: lon}
         }
 
+        // Web Mercator, v: 0.0 at north edge (85.0511 degree) ~ 1.0 at south edge
         pub fn latlon(coord: MapCoord) -> LatLon {
-            LatLon::new(( (1f64 - coord.v)).tanh().asin() * 180f64 / PI, coord.u * 360f64 - 180f64)
+            LatLon::new(((1f64 - 2f64 * coord.v) * PI).tanh().asin() * 180f64 / PI, coord.u * 360f64 - 180f64)
         }
 
         pub fn coord(&self) -> MapCoord {
-            MapCoord{u: (self.lon + 180f64) / 360f64, v: 1f64 - (self.lat * PI / 180f64).sin().atanh()}
+            let lat = self.lat.clamp(-MAX_LATITUDE, MAX_LATITUDE);
+            MapCoord{u: (self.lon + 180f64) / 360f64, v: (1f64 - (lat * PI / 180f64).sin().atanh() / PI) / 2f64}
         }
 
         pub fn radian(&self) -> (f64, f64) {
@@ -111,10 +331,126 @@ pub mod geometry_core {
             return  (dx * dx + dy * dy + dz * dz).sqrt() * RADIUS;
         }
 
+        // Haversine distance on the sphere of the WGS84 mean radius
+        pub fn great_circle_distance(&self, target: &LatLon) -> f64 {
+            let (phi1, lambda1) = self.radian();
+            let (phi2, lambda2) = target.radian();
+            let s = ((phi2 - phi1) / 2f64).sin().powi(2)
+                + phi1.cos() * phi2.cos() * ((lambda2 - lambda1) / 2f64).sin().powi(2);
+            2f64 * s.sqrt().min(1f64).asin() * Ellipsoid::WGS84.mean_radius()
+        }
+
+        pub fn geodesic_distance(&self, target: &LatLon) -> f64 {
+            Ellipsoid::WGS84.inverse(self, target).distance
+        }
+
+        pub fn distance_by(&self, target: &LatLon, model: DistanceModel) -> f64 {
+            model.distance(self, target)
+        }
+
+        // Compass bearing toward the point (same as initial_bearing)
         pub fn direction(self, point: &LatLon) -> f64 {
-            let lat = point.lat - self.lat;
-            let lon = point.lon - self.lon;
-            return (lat / lon).atan();
+            self.initial_bearing(point)
+        }
+
+        // Great circle bearing at this location toward the point, degrees clockwise from north (0.0 ~ 360.0)
+        pub fn initial_bearing(&self, point: &LatLon) -> f64 {
+            let (phi1, lambda1) = self.radian();
+            let (phi2, lambda2) = point.radian();
+            let dl = lambda2 - lambda1;
+            let y = dl.sin() * phi2.cos();
+            let x = phi1.cos() * phi2.sin() - phi1.sin() * phi2.cos() * dl.cos();
+            (y.atan2(x).to_degrees() + 360f64) % 360f64
+        }
+
+        // Great circle bearing on arrival at the point
+        pub fn final_bearing(&self, point: &LatLon) -> f64 {
+            (point.initial_bearing(self) + 180f64) % 360f64
+        }
+
+        // Location reached by travelling the distance (m) along the great circle of the bearing (degree)
+        pub fn destination(&self, bearing: f64, distance: f64) -> LatLon {
+            let (phi1, lambda1) = self.radian();
+            let theta = bearing.to_radians();
+            let delta = distance / Ellipsoid::WGS84.mean_radius();
+            let phi2 = (phi1.sin() * delta.cos() + phi1.cos() * delta.sin() * theta.cos()).asin();
+            let lambda2 = lambda1
+                + (theta.sin() * delta.sin() * phi1.cos()).atan2(delta.cos() - phi1.sin() * phi2.sin());
+            LatLon::new(phi2.to_degrees(), (lambda2.to_degrees() + 540f64) % 360f64 - 180f64)
+        }
+
+        pub fn midpoint(&self, point: &LatLon) -> LatLon {
+            self.intermediate(point, 0.5)
+        }
+
+        // Location at the fraction (0.0 = self ~ 1.0 = point) along the great circle
+        pub fn intermediate(&self, point: &LatLon, fraction: f64) -> LatLon {
+            let (phi1, lambda1) = self.radian();
+            let (phi2, lambda2) = point.radian();
+            let s = ((phi2 - phi1) / 2f64).sin().powi(2)
+                + phi1.cos() * phi2.cos() * ((lambda2 - lambda1) / 2f64).sin().powi(2);
+            let delta = 2f64 * s.sqrt().min(1f64).asin();
+            if delta == 0f64 {
+                return *self;
+            }
+            let a = ((1f64 - fraction) * delta).sin() / delta.sin();
+            let b = (fraction * delta).sin() / delta.sin();
+            let x = a * phi1.cos() * lambda1.cos() + b * phi2.cos() * lambda2.cos();
+            let y = a * phi1.cos() * lambda1.sin() + b * phi2.cos() * lambda2.sin();
+            let z = a * phi1.sin() + b * phi2.sin();
+            LatLon::new(z.atan2((x * x + y * y).sqrt()).to_degrees(), y.atan2(x).to_degrees())
+        }
+
+        // Signed distance (m) from the great circle through start and end, positive on the right side
+        pub fn cross_track_distance(&self, start: &LatLon, end: &LatLon) -> f64 {
+            let n = normalize(cross(start.position(), end.position()));
+            if n == (0f64, 0f64, 0f64) {
+                return self.great_circle_distance(start);
+            }
+            dot(self.position(), n).clamp(-1f64, 1f64).asin() * Ellipsoid::WGS84.mean_radius()
+        }
+
+        // Closest location on the great circle arc from start to end and its fraction along the arc
+        // (0.0 = start ~ 1.0 = end), the ends of the arc are the closest when the foot falls outside
+        pub fn closest_on_arc(&self, start: &LatLon, end: &LatLon) -> (LatLon, f64) {
+            let (a, b, p) = (start.position(), end.position(), self.position());
+            let n = normalize(cross(a, b));
+            if n == (0f64, 0f64, 0f64) {
+                return (*start, 0f64);
+            }
+            let length = dot(cross(a, b), n).atan2(dot(a, b));
+            let h = dot(p, n);
+            let foot = normalize((p.0 - h * n.0, p.1 - h * n.1, p.2 - h * n.2));
+            if foot != (0f64, 0f64, 0f64) {
+                let angle = dot(cross(a, foot), n).atan2(dot(a, foot));
+                if (0f64..=length).contains(&angle) {
+                    let fraction = angle / length;
+                    return (start.intermediate(end, fraction), fraction);
+                }
+            }
+            if self.great_circle_distance(start) <= self.great_circle_distance(end) {
+                (*start, 0f64)
+            } else {
+                (*end, 1f64)
+            }
+        }
+    }
+
+    // Vectors of position() on the unit sphere
+    fn cross(a: (f64, f64, f64), b: (f64, f64, f64)) -> (f64, f64, f64) {
+        (a.1 * b.2 - a.2 * b.1, a.2 * b.0 - a.0 * b.2, a.0 * b.1 - a.1 * b.0)
+    }
+
+    pub(crate) fn dot(a: (f64, f64, f64), b: (f64, f64, f64)) -> f64 {
+        a.0 * b.0 + a.1 * b.1 + a.2 * b.2
+    }
+
+    fn normalize(a: (f64, f64, f64)) -> (f64, f64, f64) {
+        let l = dot(a, a).sqrt();
+        if l < 1e-15 {
+            (0f64, 0f64, 0f64)
+        } else {
+            (a.0 / l, a.1 / l, a.2 / l)
         }
     }
 
@@ -146,6 +482,96 @@ pub mod geometry_core {
             let s: f64 = 1f64 / (2i32.pow(z)) as f64;
             TileCoord{tx: (c.u / s).trunc() as i64, ty: (c.v / s).trunc() as i64, x: (c.u % s) / s, y: (c.v % s) / s, z: z}
         }
+
+        pub fn new(tx: i64, ty: i64, z: u32) -> TileCoord {
+            TileCoord{tx, ty, x: 0f64, y: 0f64, z}
+        }
+
+        pub fn tms_y(&self) -> i64 {
+            2i64.pow(self.z) - 1 - self.ty
+        }
+
+        pub fn from_tms(tx: i64, ty: i64, z: u32) -> TileCoord {
+            TileCoord::new(tx, 2i64.pow(z) - 1 - ty, z)
+        }
+
+        pub fn quadkey(&self) -> String {
+            let mut key = String::new();
+            for i in (1..=self.z).rev() {
+                let mask = 1i64 << (i - 1);
+                let mut digit = b'0';
+                if self.tx & mask != 0 { digit += 1; }
+                if self.ty & mask != 0 { digit += 2; }
+                key.push(digit as char);
+            }
+            key
+        }
+
+        pub fn from_quadkey(key: &str) -> Option<TileCoord> {
+            let mut tx = 0i64;
+            let mut ty = 0i64;
+            for c in key.bytes() {
+                tx <<= 1;
+                ty <<= 1;
+                match c {
+                    b'0' => {}
+                    b'1' => { tx |= 1; }
+                    b'2' => { ty |= 1; }
+                    b'3' => { tx |= 1; ty |= 1; }
+                    _ => { return None; }
+                }
+            }
+            Some(TileCoord::new(tx, ty, key.len() as u32))
+        }
+
+        pub fn parent(&self) -> Option<TileCoord> {
+            if self.z == 0 {
+                return None;
+            }
+            Some(TileCoord::new(self.tx >> 1, self.ty >> 1, self.z - 1))
+        }
+
+        // North west, north east, south west, south east
+        pub fn children(&self) -> [TileCoord; 4] {
+            let (x, y, z) = (self.tx * 2, self.ty * 2, self.z + 1);
+            [TileCoord::new(x, y, z), TileCoord::new(x + 1, y, z), TileCoord::new(x, y + 1, z), TileCoord::new(x + 1, y + 1, z)]
+        }
+
+        // Tile moved by (dx, dy), x wraps around the 180 degree meridian and y stops at the poles
+        pub fn neighbour(&self, dx: i64, dy: i64) -> Option<TileCoord> {
+            let n = 2i64.pow(self.z);
+            let ty = self.ty + dy;
+            if (ty < 0) || (ty >= n) {
+                return None;
+            }
+            Some(TileCoord::new((self.tx + dx).rem_euclid(n), ty, self.z))
+        }
+
+        // 8 surrounding tiles, fewer at the poles and at zoom 0
+        pub fn neighbours(&self) -> Vec<TileCoord> {
+            let mut list: Vec<TileCoord> = Vec::new();
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    if (dx == 0) && (dy == 0) {
+                        continue;
+                    }
+                    if let Some(t) = self.neighbour(dx, dy) {
+                        if (t.tx != self.tx || t.ty != self.ty) && !list.contains(&t) {
+                            list.push(t);
+                        }
+                    }
+                }
+            }
+            list
+        }
+
+        // Tile index containing the location, clamped into 0 ~ 2^z - 1
+        fn index(l: &LatLon, z: u32) -> (i64, i64) {
+            let c = l.coord();
+            let n = 2f64.powi(z as i32);
+            let max = n as i64 - 1;
+            (((c.u * n).floor() as i64).clamp(0, max), ((c.v * n).floor() as i64).clamp(0, max))
+        }
     }
 
     impl fmt::Display for TileCoord {
@@ -154,6 +580,184 @@ pub mod geometry_core {
         }
     }
 
+    impl PixelScale {
+        pub const STANDARD: PixelScale = PixelScale{tile_size: 256, ratio: 1f64};
+        pub const TILE_512: PixelScale = PixelScale{tile_size: 512, ratio: 1f64};
+
+        pub fn new(tile_size: u32, ratio: f64) -> PixelScale {
+            PixelScale{tile_size, ratio}
+        }
+
+        // Size of a tile in device pixel
+        pub fn tile_pixels(&self) -> f64 {
+            self.tile_size as f64 * self.ratio
+        }
+
+        // Size of the whole world in device pixel at zoom z
+        pub fn world_pixels(&self, z: u32) -> f64 {
+            self.tile_pixels() * 2f64.powi(z as i32)
+        }
+    }
+
+    impl PixelCoord {
+        pub fn from_coord(c: &MapCoord, z: u32, scale: PixelScale) -> PixelCoord {
+            let size = scale.world_pixels(z);
+            PixelCoord{x: c.u * size, y: c.v * size, z, scale}
+        }
+
+        pub fn from_latlon(l: &LatLon, z: u32, scale: PixelScale) -> PixelCoord {
+            Self::from_coord(&l.coord(), z, scale)
+        }
+
+        pub fn from_tile(t: &TileCoord, scale: PixelScale) -> PixelCoord {
+            let size = scale.tile_pixels();
+            PixelCoord{x: (t.tx as f64 + t.x) * size, y: (t.ty as f64 + t.y) * size, z: t.z, scale}
+        }
+
+        pub fn coord(&self) -> MapCoord {
+            let size = self.scale.world_pixels(self.z);
+            MapCoord{u: self.x / size, v: self.y / size}
+        }
+
+        pub fn latlon(&self) -> LatLon {
+            LatLon::latlon(self.coord())
+        }
+
+        pub fn tile(&self) -> TileCoord {
+            let size = self.scale.tile_pixels();
+            let tx = (self.x / size).floor();
+            let ty = (self.y / size).floor();
+            TileCoord{tx: tx as i64, ty: ty as i64, x: self.x / size - tx, y: self.y / size - ty, z: self.z}
+        }
+
+        // Same location at another zoom level
+        pub fn zoom(&self, z: u32) -> PixelCoord {
+            let f = 2f64.powi(z as i32 - self.z as i32);
+            PixelCoord{x: self.x * f, y: self.y * f, z, scale: self.scale}
+        }
+
+        // Whole device pixel containing this coordinate
+        pub fn pixel(&self) -> (i64, i64) {
+            (self.x.floor() as i64, self.y.floor() as i64)
+        }
+
+        // Distance in device pixel, for hit testing
+        pub fn distance(&self, p: &PixelCoord) -> f64 {
+            let p = p.zoom(self.z);
+            ((self.x - p.x).powi(2) + (self.y - p.y).powi(2)).sqrt()
+        }
+    }
+
+    impl fmt::Display for PixelCoord {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "Pixel(x:{}, y:{}) Zoom: {} Tile: {}px x{}", self.x, self.y, self.z, self.scale.tile_size, self.scale.ratio)
+        }
+    }
+
+    // Rectangle of tiles at zoom z, west > east means the range wraps around the 180 degree meridian
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct TileRange {
+        pub z: u32,
+        pub west: i64,
+        pub east: i64,
+        pub north: i64,
+        pub south: i64,
+    }
+
+    impl TileRange {
+        pub fn from_area(area: &Area, z: u32) -> Option<TileRange> {
+            if !area.is_valid() {
+                return None;
+            }
+            let (mut west, north) = TileCoord::index(&LatLon::new(area.north(), area.west()), z);
+            let (mut east, south) = TileCoord::index(&LatLon::new(area.south(), area.east()), z);
+            if area.span().1 >= 360f64 {
+                west = 0;
+                east = 2i64.pow(z) - 1;
+            }
+            Some(TileRange{z, west, east, north, south})
+        }
+
+        pub fn width(&self) -> u64 {
+            if self.west <= self.east {
+                (self.east - self.west + 1) as u64
+            } else {
+                (2i64.pow(self.z) - self.west + self.east + 1) as u64
+            }
+        }
+
+        pub fn count(&self) -> u64 {
+            self.width() * (self.south - self.north + 1) as u64
+        }
+
+        pub fn iter(&self) -> impl Iterator<Item = TileCoord> + '_ {
+            let n = 2i64.pow(self.z);
+            (0..self.width() as i64).flat_map(move |i| {
+                (self.north..=self.south).map(move |ty| TileCoord{tx: (self.west + i) % n, ty, x: 0f64, y: 0f64, z: self.z})
+            })
+        }
+    }
+
+    // Tiles over a zoom range, either rectangles (areas) or an explicit set (corridors)
+    #[derive(Clone, Debug)]
+    pub struct TileCoverage {
+        ranges: Vec<TileRange>,
+        tiles: BTreeSet<(u32, i64, i64)>,
+    }
+
+    impl TileCoverage {
+        pub fn from_area(area: &Area, zoom: RangeInclusive<u32>) -> TileCoverage {
+            let ranges = zoom.filter_map(|z| TileRange::from_area(area, z)).collect();
+            TileCoverage{ranges, tiles: BTreeSet::new()}
+        }
+
+        // Tiles within the buffer distance (m) of the polyline.
+        // The line is sampled at a half of the tile size and the tiles around every sample are taken.
+        pub fn from_corridor(line: &[LatLon], buffer: f64, zoom: RangeInclusive<u32>) -> TileCoverage {
+            let mut tiles = BTreeSet::new();
+            for z in zoom {
+                let mut add = |l: &LatLon, margin: f64| {
+                    let area = Area::new(l.lat, l.lon, l.lat, l.lon).expand(margin);
+                    if let Some(r) = TileRange::from_area(&area, z) {
+                        for t in r.iter() {
+                            tiles.insert((z, t.tx, t.ty));
+                        }
+                    }
+                };
+                if line.len() == 1 {
+                    add(&line[0], buffer);
+                }
+                for w in line.windows(2) {
+                    let (a, b) = (&w[0], &w[1]);
+                    let lat = a.lat.abs().max(b.lat.abs()).min(MAX_LATITUDE);
+                    let tile = 2f64 * PI * RADIUS * lat.to_radians().cos() / 2f64.powi(z as i32);
+                    let step = tile / 2f64;
+                    let d = a.great_circle_distance(b);
+                    let n = (d / step).ceil().max(1f64) as usize;
+                    for i in 0..=n {
+                        add(&a.intermediate(b, i as f64 / n as f64), buffer + step / 2f64);
+                    }
+                }
+            }
+            TileCoverage{ranges: Vec::new(), tiles}
+        }
+
+        pub fn count(&self) -> u64 {
+            self.ranges.iter().map(|r| r.count()).sum::<u64>() + self.tiles.len() as u64
+        }
+
+        pub fn iter(&self) -> impl Iterator<Item = TileCoord> + '_ {
+            self.ranges.iter().flat_map(|r| r.iter())
+                .chain(self.tiles.iter().map(|(z, tx, ty)| TileCoord{tx: *tx, ty: *ty, x: 0f64, y: 0f64, z: *z}))
+        }
+    }
+
+    impl Area {
+        pub fn tiles(&self, zoom: RangeInclusive<u32>) -> TileCoverage {
+            TileCoverage::from_area(self, zoom)
+        }
+    }
+
     #[derive(Clone, Debug)]
     pub struct Geometry {
         pub location: LatLon,
@@ -161,18 +765,24 @@ pub mod geometry_core {
     }
 
     impl Geometry {
+        // Great circle distance, acos() of the dot product loses precision for short distances so use haversine
         pub fn horizontal_distance(&self, p: &Geometry) -> f64 {
-            let a = self.location.position();
-            let b = p.location.position();
-            // Simplified because (a.0 * a.0 + a.1 * a.1 + a.2 * a.2).sqrt() * (b.0 * b.0 + b.1 * b.1 * b.1 + b.2 * b.2).sqrt() ~= 1
-            let cos = a.0 * b.0 + a.1 * b.1 + a.2 * b.2;
-            cos.acos() * RADIUS
+            self.location.great_circle_distance(&p.location)
         }
 
         pub fn distance(&self, p: &Geometry) -> f64 {
             let d = self.horizontal_distance(p);
-            let h = (self.alt - p.alt).abs();
-            let min = self.alt.min(p.alt);
+            self.slope_distance(p, d)
+        }
+
+        pub fn distance_by(&self, p: &Geometry, model: DistanceModel) -> f64 {
+            let d = self.location.distance_by(&p.location, model);
+            self.slope_distance(p, d)
+        }
+
+        // Unknown altitude is treated as flat
+        fn slope_distance(&self, p: &Geometry, d: f64) -> f64 {
+            let h = if self.alt.is_nan() || p.alt.is_nan() { 0f64 } else { (self.alt - p.alt).abs() };
             (d * d + h * h).sqrt()
         }
 
@@ -183,4 +793,303 @@ pub mod geometry_core {
             1000f64 * h / d
         }
     }
+
+    // Ring of locations, the last location may or may not repeat the first one.
+    // Holes are ignored by point-in-polygon and subtracted from the area.
+    #[derive(Clone, Debug)]
+    pub struct Polygon {
+        pub exterior: Vec<LatLon>,
+        pub holes: Vec<Vec<LatLon>>,
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct MultiPolygon {
+        pub polygons: Vec<Polygon>,
+    }
+
+    impl Polygon {
+        pub fn new(exterior: Vec<LatLon>) -> Polygon {
+            Self {exterior, holes: Vec::new()}
+        }
+
+        pub fn add_hole(self: &mut Self, hole: Vec<LatLon>) {
+            self.holes.push(hole);
+        }
+
+        pub fn contains(&self, location: &LatLon) -> bool {
+            if !ring_contains(&self.exterior, location) {
+                return false;
+            }
+            !self.holes.iter().any(|h| ring_contains(h, location))
+        }
+
+        // Area on the WGS84 ellipsoid in square metre
+        pub fn area(&self) -> f64 {
+            let mut a = ring_area(&self.exterior);
+            for h in &self.holes {
+                a -= ring_area(h);
+            }
+            a.max(0f64)
+        }
+
+        // Geodesic length of the exterior ring in metre
+        pub fn perimeter(&self) -> f64 {
+            ring_perimeter(&self.exterior)
+        }
+
+        pub fn bounds(&self) -> Area {
+            let mut area = Area::invalid();
+            for l in &self.exterior {
+                area.enter(l);
+            }
+            area
+        }
+    }
+
+    impl MultiPolygon {
+        pub fn new() -> MultiPolygon {
+            Self {polygons: Vec::new()}
+        }
+
+        pub fn contains(&self, location: &LatLon) -> bool {
+            self.polygons.iter().any(|p| p.contains(location))
+        }
+
+        pub fn area(&self) -> f64 {
+            self.polygons.iter().map(|p| p.area()).sum()
+        }
+
+        pub fn perimeter(&self) -> f64 {
+            self.polygons.iter().map(|p| p.perimeter()).sum()
+        }
+
+        pub fn bounds(&self) -> Area {
+            let mut area = Area::invalid();
+            for p in &self.polygons {
+                area.add(&p.bounds());
+            }
+            area
+        }
+    }
+
+    // Edges of the ring without the closing duplicate
+    fn ring_edges(ring: &[LatLon]) -> impl Iterator<Item = (&LatLon, &LatLon)> {
+        let n = if (ring.len() > 1) && (ring[0].lat == ring[ring.len() - 1].lat) && (ring[0].lon == ring[ring.len() - 1].lon) {
+            ring.len() - 1
+        } else {
+            ring.len()
+        };
+        (0..n).map(move |i| (&ring[i], &ring[(i + 1) % n]))
+    }
+
+    // Ray casting on the ring unwrapped to continuous longitudes, so rings across the 180 degree meridian work
+    fn ring_contains(ring: &[LatLon], location: &LatLon) -> bool {
+        let mut unwrapped: Vec<(f64, f64)> = Vec::new();
+        let mut x = 0f64;
+        for (i, (a, b)) in ring_edges(ring).enumerate() {
+            if i == 0 {
+                x = a.lon;
+                unwrapped.push((x, a.lat));
+            }
+            x += wrap_lon(b.lon - a.lon);
+            unwrapped.push((x, b.lat));
+        }
+        [-360f64, 0f64, 360f64].iter().any(|shift| {
+            let (px, py) = (location.lon + shift, location.lat);
+            let mut inside = false;
+            for w in unwrapped.windows(2) {
+                let ((ax, ay), (bx, by)) = (w[0], w[1]);
+                if (ay > py) != (by > py) {
+                    let cx = ax + (bx - ax) * (py - ay) / (by - ay);
+                    if cx > px {
+                        inside = !inside;
+                    }
+                }
+            }
+            inside
+        })
+    }
+
+    // Spherical excess on the authalic sphere, latitudes converted to authalic latitude
+    fn ring_area(ring: &[LatLon]) -> f64 {
+        let e2 = Ellipsoid::WGS84.e2();
+        let e = e2.sqrt();
+        let q = |phi: f64| {
+            let s = phi.sin();
+            (1f64 - e2) * (s / (1f64 - e2 * s * s) - (1f64 / (2f64 * e)) * ((1f64 - e * s) / (1f64 + e * s)).ln())
+        };
+        let qp = q(PI / 2f64);
+        let r2 = Ellipsoid::WGS84.a * Ellipsoid::WGS84.a * qp / 2f64;
+        let authalic = |phi: f64| (q(phi) / qp).clamp(-1f64, 1f64).asin();
+
+        let mut excess = 0f64;
+        for (a, b) in ring_edges(ring) {
+            let (phi1, lambda1) = a.radian();
+            let (phi2, lambda2) = b.radian();
+            let dl = wrap_lon((lambda2 - lambda1).to_degrees()).to_radians();
+            let t1 = (authalic(phi1) / 2f64).tan();
+            let t2 = (authalic(phi2) / 2f64).tan();
+            excess += 2f64 * ((dl / 2f64).tan() * (t1 + t2)).atan2(1f64 + t1 * t2);
+        }
+        (excess * r2).abs()
+    }
+
+    fn ring_perimeter(ring: &[LatLon]) -> f64 {
+        ring_edges(ring).map(|(a, b)| a.geodesic_distance(b)).sum()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn dms(d: f64, m: f64, s: f64) -> f64 {
+            d.signum() * (d.abs() + m / 60f64 + s / 3600f64)
+        }
+
+        // Examples of Movable Type Scripts, "Calculate distance, bearing and more between Latitude/Longitude points"
+        #[test]
+        fn bearings() {
+            let lands_end = LatLon::new(dms(50f64, 3f64, 59f64), dms(-5f64, 42f64, 53f64));
+            let john_o_groats = LatLon::new(dms(58f64, 38f64, 38f64), dms(-3f64, 4f64, 12f64));
+            assert!((lands_end.great_circle_distance(&john_o_groats) - 968.9e3).abs() < 100f64);
+            assert!((lands_end.initial_bearing(&john_o_groats) - dms(9f64, 7f64, 11f64)).abs() < 3e-4);
+            assert!((lands_end.final_bearing(&john_o_groats) - dms(11f64, 16f64, 31f64)).abs() < 3e-4);
+            let m = lands_end.midpoint(&john_o_groats);
+            assert!((m.lat - dms(54f64, 21f64, 44f64)).abs() < 3e-4 && (m.lon - dms(-4f64, 31f64, 50f64)).abs() < 3e-4);
+            // Both ends of intermediate are the points themselves
+            let (a, b) = (lands_end.intermediate(&john_o_groats, 0f64), lands_end.intermediate(&john_o_groats, 1f64));
+            assert!((a.lat - lands_end.lat).abs() < 1e-9 && (a.lon - lands_end.lon).abs() < 1e-9);
+            assert!((b.lat - john_o_groats.lat).abs() < 1e-9 && (b.lon - john_o_groats.lon).abs() < 1e-9);
+
+            let start = LatLon::new(dms(53f64, 19f64, 14f64), dms(-1f64, 43f64, 47f64));
+            let d = start.destination(dms(96f64, 1f64, 18f64), 124.8e3);
+            assert!((d.lat - dms(53f64, 11f64, 18f64)).abs() < 3e-4 && (d.lon - dms(0f64, 8f64, 0f64)).abs() < 3e-4);
+            // Back along the final bearing reversed
+            let r = d.destination((start.final_bearing(&d) + 180f64) % 360f64, 124.8e3);
+            assert!(r.great_circle_distance(&start) < 1e-3);
+
+            // Due north and due west, no atan(dlat/dlon)
+            assert!(start.initial_bearing(&LatLon::new(54f64, start.lon)).abs() < 1e-9);
+            assert!((LatLon::new(0f64, 10f64).initial_bearing(&LatLon::new(0f64, 9f64)) - 270f64).abs() < 1e-9);
+        }
+
+        #[test]
+        fn area_across_antimeridian() {
+            let mut a = Area::invalid();
+            for lon in [178.5, 179.5, -179.5, -178.0] {
+                a.enter(&LatLon::new(10f64, lon));
+            }
+            assert!(a.crosses_antimeridian());
+            assert!((a.west() - 178.5).abs() < 1e-12 && (a.east() + 178.0).abs() < 1e-12);
+            assert!((a.span().1 - 3.5).abs() < 1e-9);
+            assert!((a.center().lon + 179.75).abs() < 1e-9);
+            assert!(a.contains(&LatLon::new(10f64, 180f64)));
+            assert!(!a.contains(&LatLon::new(10f64, 0f64)));
+        }
+
+        #[test]
+        fn area_set_operations() {
+            let a = Area::new(40f64, 170f64, 30f64, -170f64);
+            let b = Area::new(35f64, -175f64, 20f64, -160f64);
+            let c = Area::new(10f64, 0f64, 0f64, 10f64);
+            let i = a.intersection(&b).unwrap();
+            assert_eq!((i.north(), i.west(), i.south(), i.east()), (35f64, -175f64, 30f64, -170f64));
+            assert!(!a.intersects(&c));
+            let u = a.union(&b);
+            assert_eq!((u.north(), u.west(), u.south(), u.east()), (40f64, 170f64, 20f64, -160f64));
+            assert!(u.contains_area(&a) && u.contains_area(&b));
+            assert!(a.is_in(&u));
+            let e = c.expand(111195f64);
+            assert!((e.north() - 11f64).abs() < 1e-3 && (e.south() + 1f64).abs() < 1e-3);
+            assert!(e.contains_area(&c));
+        }
+
+        #[test]
+        fn polygon() {
+            // 1 degree square on the equator with a hole
+            let ring = vec![LatLon::new(0f64, 0f64), LatLon::new(0f64, 1f64), LatLon::new(1f64, 1f64), LatLon::new(1f64, 0f64), LatLon::new(0f64, 0f64)];
+            let mut p = Polygon::new(ring);
+            // The area of 1 degree square at the equator on WGS84 is 12308778361 m^2
+            assert!((p.area() / 12308778361f64 - 1f64).abs() < 1e-3);
+            assert!(p.contains(&LatLon::new(0.5, 0.5)));
+            p.add_hole(vec![LatLon::new(0.4, 0.4), LatLon::new(0.4, 0.6), LatLon::new(0.6, 0.6), LatLon::new(0.6, 0.4)]);
+            assert!(!p.contains(&LatLon::new(0.5, 0.5)));
+            assert!(p.contains(&LatLon::new(0.2, 0.5)));
+            assert!((p.perimeter() - 443.77e3).abs() < 100f64);
+
+            // Across the 180 degree meridian
+            let p = Polygon::new(vec![LatLon::new(-1f64, 179f64), LatLon::new(-1f64, -179f64), LatLon::new(1f64, -179f64), LatLon::new(1f64, 179f64)]);
+            assert!(p.contains(&LatLon::new(0f64, 180f64)));
+            assert!(!p.contains(&LatLon::new(0f64, 0f64)));
+            assert!(p.bounds().crosses_antimeridian());
+            assert!((p.area() / (4f64 * 12308778361f64) - 1f64).abs() < 1e-2);
+        }
+
+        #[test]
+        fn web_mercator_round_trip() {
+            for l in [LatLon::new(35.681236, 139.767125), LatLon::new(-33.856784, 151.215297), LatLon::new(80f64, -170f64), LatLon::new(0f64, 0f64)] {
+                let r = LatLon::latlon(l.coord());
+                assert!((r.lat - l.lat).abs() < 1e-9 && (r.lon - l.lon).abs() < 1e-9);
+            }
+            assert!((LatLon::latlon(MapCoord{u: 0.5, v: 0f64}).lat - MAX_LATITUDE).abs() < 1e-6);
+            assert!((LatLon::latlon(MapCoord{u: 0.5, v: 1f64}).lat + MAX_LATITUDE).abs() < 1e-6);
+        }
+
+        #[test]
+        fn tile_coverage() {
+            let t = TileCoord::tile_from_latlon(&LatLon::new(35.681236, 139.767125), 8);
+            assert_eq!((t.tx, t.ty), (227, 100));
+
+            let area = Area::new(36f64, 139f64, 35f64, 140f64);
+            let c = area.tiles(8..=10);
+            assert_eq!(c.count(), c.iter().count() as u64);
+
+            // Pacific crossing the 180 degree meridian at zoom 2 takes x = 3 and x = 0
+            let c = Area::new(10f64, 170f64, -10f64, -170f64).tiles(2..=2);
+            let mut x: Vec<i64> = c.iter().map(|t| t.tx).collect();
+            x.dedup();
+            assert_eq!(x, vec![3, 0]);
+
+            // Diagonal traverse needs far fewer tiles than its bounding box
+            let line = vec![LatLon::new(35.0, 138.0), LatLon::new(36.0, 139.0)];
+            let corridor = TileCoverage::from_corridor(&line, 100f64, 12..=14);
+            let mut bounds = Area::invalid();
+            bounds.enter(&line[0]);
+            bounds.enter(&line[1]);
+            assert!(corridor.count() * 3 < bounds.tiles(12..=14).count());
+            assert_eq!(corridor.count(), corridor.iter().count() as u64);
+        }
+
+        #[test]
+        fn pixel_coord() {
+            let l = LatLon::new(35.681236, 139.767125);
+            let p = PixelCoord::from_latlon(&l, 8, PixelScale::STANDARD);
+            let t = p.tile();
+            assert_eq!((t.tx, t.ty), (227, 100));
+            let r = p.latlon();
+            assert!((r.lat - l.lat).abs() < 1e-9 && (r.lon - l.lon).abs() < 1e-9);
+
+            // 512px tiles on a 2x display have 4 times the pixels of 256px tiles
+            let q = PixelCoord::from_latlon(&l, 8, PixelScale::new(512, 2f64));
+            assert!((q.x - p.x * 4f64).abs() < 1e-6 && (q.y - p.y * 4f64).abs() < 1e-6);
+            let b = PixelCoord::from_tile(&q.tile(), q.scale);
+            assert!(b.distance(&q) < 1e-6);
+            assert!((p.zoom(9).x - p.x * 2f64).abs() < 1e-9);
+        }
+
+        #[test]
+        fn tile_addressing() {
+            let t = TileCoord::new(3, 5, 3);
+            assert_eq!(t.quadkey(), "213");
+            assert_eq!(TileCoord::from_quadkey("213"), Some(t));
+            assert!(TileCoord::from_quadkey("214").is_none());
+            assert_eq!(t.tms_y(), 2);
+            assert_eq!(TileCoord::from_tms(3, 2, 3), t);
+            assert_eq!(t.parent(), Some(TileCoord::new(1, 2, 2)));
+            assert!(t.children().iter().all(|c| c.parent() == Some(t)));
+            assert_eq!(TileCoord::new(0, 0, 2).neighbour(-1, 0), Some(TileCoord::new(3, 0, 2)));
+            assert_eq!(TileCoord::new(0, 0, 2).neighbours().len(), 5);
+            assert_eq!(t.neighbours().len(), 8);
+        }
+    }
 }
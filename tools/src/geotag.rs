@@ -0,0 +1,572 @@
+//
+// Photo geotagging tools
+//
+// -- EXIF -- (JPEG APP1 "Exif", TIFF in either byte order)
+// [Read] DateTimeOriginal (0x9003) or DateTime (0x0132) when missing, OffsetTimeOriginal (0x9011), and the GPS IFD
+// [Write] GPS IFD with version, latitude, longitude, altitude, time stamp and date stamp (UTC).
+//         The old data stays where it is, so offsets inside the maker notes are kept.
+//         The GPS IFD already in the photo is overwritten when the new one fits in its place,
+//         else the new GPS IFD and a new IFD0 pointing to it are appended.
+//         The photo is written to a temporary file next to it and renamed over it.
+//
+// The camera time is local, OffsetTimeOriginal gives the zone, time_zone is taken when the photo has none.
+// clock_offset is how far the camera clock runs ahead of the GPS, position_at looks up the track at time - offset.
+//
+pub mod geotag {
+    use std::fs;
+    use std::io;
+    use std::path::{Path, PathBuf};
+    use chrono::{DateTime, Datelike, Duration, FixedOffset, NaiveDateTime, Timelike, Utc};
+    use crate::geometry::geometry_core::LatLon;
+    use crate::gpx::gpx::{is_known_time, Track, TrackPoint};
+
+    const EXIF_HEADER: &[u8] = b"Exif\0\0";
+    const TAG_DATE_TIME: u16 = 0x0132;
+    const TAG_EXIF_IFD: u16 = 0x8769;
+    const TAG_GPS_IFD: u16 = 0x8825;
+    const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+    const TAG_OFFSET_TIME_ORIGINAL: u16 = 0x9011;
+
+    const BYTE: u16 = 1;
+    const ASCII: u16 = 2;
+    const LONG: u16 = 4;
+    const RATIONAL: u16 = 5;
+
+    #[derive(Debug, Clone)]
+    pub struct Exif {
+        pub date_time_original: Option<NaiveDateTime>,  // Camera local time
+        pub offset_time: Option<FixedOffset>,
+        pub gps: Option<LatLon>,
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct Photo {
+        pub path: PathBuf,
+        pub time: DateTime<Utc>,        // Camera time in UTC, MIN_UTC without EXIF time
+        pub point: Option<TrackPoint>,  // Position on the track, None outside the track
+    }
+
+    #[derive(Debug)]
+    pub struct TagReport {
+        pub written: usize,
+        pub failed: Vec<(PathBuf, io::Error)>,  // The photos are left as they were
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct Geotagger {
+        pub time_zone: FixedOffset,
+        pub clock_offset: Duration,
+    }
+
+    impl Geotagger {
+        pub fn new(time_zone: FixedOffset) -> Geotagger {
+            Self {time_zone, clock_offset: Duration::zero()}
+        }
+
+        pub fn set_clock_offset(self: &mut Self, offset: Duration) {
+            self.clock_offset = offset;
+        }
+
+        // JPEG files in the folder sorted by name, with their positions on the track
+        pub fn locate(&self, track: &Track, dir: &Path) -> io::Result<Vec<Photo>> {
+            let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.extension().and_then(|e| e.to_str())
+                    .is_some_and(|e| e.eq_ignore_ascii_case("jpg") || e.eq_ignore_ascii_case("jpeg")))
+                .collect();
+            paths.sort();
+            let mut photos = Vec::with_capacity(paths.len());
+            for path in paths {
+                let exif = read_exif(&fs::read(&path)?);
+                let time = exif.as_ref().and_then(|e| {
+                    let zone = e.offset_time.unwrap_or(self.time_zone);
+                    e.date_time_original.map(|t| DateTime::<Utc>::from_naive_utc_and_offset(t, Utc) - Duration::seconds(zone.local_minus_utc() as i64))
+                }).unwrap_or(DateTime::<Utc>::MIN_UTC);
+                let point = if is_known_time(&time) { track.position_at(time, self.clock_offset) } else { None };
+                photos.push(Photo {path, time, point});
+            }
+            Ok(photos)
+        }
+
+        // Waypoints named by the file name, linked to the photo
+        pub fn waypoints(&self, photos: &[Photo]) -> Vec<TrackPoint> {
+            photos.iter().filter_map(|photo| {
+                let mut p = photo.point.clone()?;
+                p.name = photo.path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                p.link = photo.path.to_string_lossy().to_string();
+                Some(p)
+            }).collect()
+        }
+
+        // Write the positions into the photos with a position, a failed photo does not stop the others
+        pub fn write_exif(&self, photos: &[Photo]) -> TagReport {
+            let mut report = TagReport {written: 0, failed: Vec::new()};
+            for photo in photos {
+                if let Some(p) = &photo.point {
+                    match write_photo(&photo.path, p) {
+                        Ok(()) => { report.written += 1; }
+                        Err(e) => { report.failed.push((photo.path.clone(), e)); }
+                    }
+                }
+            }
+            report
+        }
+    }
+
+    impl Track {
+        // Add the waypoints of the photos in the folder, returns the number of the waypoints added
+        pub fn add_photo_waypoints(self: &mut Self, tagger: &Geotagger, dir: &Path) -> io::Result<usize> {
+            let points = tagger.waypoints(&tagger.locate(self, dir)?);
+            let n = points.len();
+            for p in points {
+                self.add_waypoint(p);
+            }
+            Ok(n)
+        }
+    }
+
+    // Through a temporary file in the same folder, so the photo is never left half written
+    fn write_photo(path: &Path, p: &TrackPoint) -> io::Result<()> {
+        let jpeg = fs::read(path)?;
+        let tagged = write_gps(&jpeg, p)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no EXIF or no room for GPS EXIF"))?;
+        let name = path.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file"))?;
+        let temp = path.with_file_name(format!(".{}.geotag", name.to_string_lossy()));
+        let result = fs::write(&temp, tagged)
+            .and_then(|_| fs::set_permissions(&temp, fs::metadata(path)?.permissions()))
+            .and_then(|_| fs::rename(&temp, path));
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        result
+    }
+
+    // TIFF block of the EXIF segment
+    struct Tiff<'a> {
+        data: &'a [u8],
+        little: bool,
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    struct Entry {
+        tag: u16,
+        kind: u16,
+        count: u32,
+        at: usize,      // Offset of the entry in the TIFF
+    }
+
+    impl<'a> Tiff<'a> {
+        fn new(data: &'a [u8]) -> Option<Tiff<'a>> {
+            let little = match data.get(0..2)? {
+                b"II" => { true }
+                b"MM" => { false }
+                _ => { return None }
+            };
+            let tiff = Tiff {data, little};
+            if tiff.u16(2)? != 42 { None } else { Some(tiff) }
+        }
+
+        fn u16(&self, at: usize) -> Option<u16> {
+            let b: [u8; 2] = self.data.get(at..at + 2)?.try_into().ok()?;
+            Some(if self.little { u16::from_le_bytes(b) } else { u16::from_be_bytes(b) })
+        }
+
+        fn u32(&self, at: usize) -> Option<u32> {
+            let b: [u8; 4] = self.data.get(at..at + 4)?.try_into().ok()?;
+            Some(if self.little { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) })
+        }
+
+        fn put_u16(&self, out: &mut Vec<u8>, v: u16) {
+            out.extend(if self.little { v.to_le_bytes() } else { v.to_be_bytes() });
+        }
+
+        fn put_u32(&self, out: &mut Vec<u8>, v: u32) {
+            out.extend(if self.little { v.to_le_bytes() } else { v.to_be_bytes() });
+        }
+
+        fn entries(&self, ifd: usize) -> Option<Vec<Entry>> {
+            let n = self.u16(ifd)? as usize;
+            (0..n).map(|i| {
+                let at = ifd + 2 + i * 12;
+                Some(Entry {tag: self.u16(at)?, kind: self.u16(at + 2)?, count: self.u32(at + 4)?, at})
+            }).collect()
+        }
+
+        // Offset of the value, inline in the entry when it fits in 4 bytes
+        fn value_at(&self, e: &Entry) -> Option<usize> {
+            if value_size(e) <= 4 { Some(e.at + 8) } else { Some(self.u32(e.at + 8)? as usize) }
+        }
+
+        // Offsets of the IFD and the values outside of it
+        fn offsets(&self, ifd: usize, entries: &[Entry]) -> Vec<usize> {
+            let mut offsets = vec![ifd];
+            offsets.extend(entries.iter().filter(|e| value_size(e) > 4).filter_map(|e| self.value_at(e)));
+            offsets
+        }
+
+        fn ascii(&self, e: &Entry) -> Option<String> {
+            let at = self.value_at(e)?;
+            let bytes = self.data.get(at..at + e.count as usize)?;
+            let text: Vec<u8> = bytes.iter().copied().take_while(|b| *b != 0).collect();
+            String::from_utf8(text).ok()
+        }
+
+        fn rationals(&self, e: &Entry) -> Option<Vec<f64>> {
+            let at = self.value_at(e)?;
+            (0..e.count as usize).map(|i| {
+                let (n, d) = (self.u32(at + i * 8)?, self.u32(at + i * 8 + 4)?);
+                Some(n as f64 / d as f64)
+            }).collect()
+        }
+    }
+
+    fn value_size(e: &Entry) -> usize {
+        (match e.kind { 3 => { 2 } 4 | 9 => { 4 } 5 | 10 => { 8 } _ => { 1 } }) * e.count as usize
+    }
+
+    // Start and end of the APP1 Exif segment (from the marker) in the JPEG
+    fn find_exif(jpeg: &[u8]) -> Option<(usize, usize)> {
+        if jpeg.get(0..2)? != [0xFF, 0xD8] {
+            return None;
+        }
+        let mut at = 2;
+        loop {
+            while *jpeg.get(at)? == 0xFF && *jpeg.get(at + 1)? == 0xFF {
+                at += 1;
+            }
+            if *jpeg.get(at)? != 0xFF {
+                return None;
+            }
+            let marker = *jpeg.get(at + 1)?;
+            if marker == 0xD9 || marker == 0xDA {
+                return None;
+            }
+            if (0xD0..=0xD7).contains(&marker) || marker == 0x01 {
+                at += 2;
+                continue;
+            }
+            let length = u16::from_be_bytes([*jpeg.get(at + 2)?, *jpeg.get(at + 3)?]) as usize;
+            let end = at + 2 + length;
+            if marker == 0xE1 && jpeg.get(at + 4..at + 10)? == EXIF_HEADER {
+                return Some((at, end.min(jpeg.len())));
+            }
+            at = end;
+        }
+    }
+
+    fn parse_offset(s: &str) -> Option<FixedOffset> {
+        let sign = match s.get(0..1)? { "+" => { 1 } "-" => { -1 } _ => { return None } };
+        let hours: i32 = s.get(1..3)?.parse().ok()?;
+        let minutes: i32 = s.get(4..6)?.parse().ok()?;
+        FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+    }
+
+    pub fn read_exif(jpeg: &[u8]) -> Option<Exif> {
+        let (start, end) = find_exif(jpeg)?;
+        let tiff = Tiff::new(jpeg.get(start + 10..end)?)?;
+        let ifd0 = tiff.entries(tiff.u32(4)? as usize)?;
+        let mut exif = Exif {date_time_original: None, offset_time: None, gps: None};
+        let parse_time = |s: String| NaiveDateTime::parse_from_str(&s, "%Y:%m:%d %H:%M:%S").ok();
+
+        if let Some(e) = ifd0.iter().find(|e| e.tag == TAG_DATE_TIME) {
+            exif.date_time_original = tiff.ascii(e).and_then(parse_time);
+        }
+        let sub = |tag: u16| ifd0.iter().find(|e| e.tag == tag).and_then(|e| tiff.u32(e.at + 8)).and_then(|o| tiff.entries(o as usize));
+        if let Some(entries) = sub(TAG_EXIF_IFD) {
+            for e in &entries {
+                match e.tag {
+                    TAG_DATE_TIME_ORIGINAL => {
+                        if let Some(t) = tiff.ascii(e).and_then(parse_time) {
+                            exif.date_time_original = Some(t);
+                        }
+                    }
+                    TAG_OFFSET_TIME_ORIGINAL => { exif.offset_time = tiff.ascii(e).and_then(|s| parse_offset(&s)); }
+                    _ => {}
+                }
+            }
+        }
+        if let Some(entries) = sub(TAG_GPS_IFD) {
+            let find = |tag: u16| entries.iter().find(|e| e.tag == tag);
+            let degree = |v: Vec<f64>| v.iter().zip([1f64, 60f64, 3600f64]).map(|(x, d)| x / d).sum::<f64>();
+            let lat = find(2).and_then(|e| tiff.rationals(e)).map(degree);
+            let lon = find(4).and_then(|e| tiff.rationals(e)).map(degree);
+            let south = find(1).and_then(|e| tiff.ascii(e)).is_some_and(|r| r == "S");
+            let west = find(3).and_then(|e| tiff.ascii(e)).is_some_and(|r| r == "W");
+            if let (Some(lat), Some(lon)) = (lat, lon) {
+                exif.gps = Some(LatLon::new(if south { -lat } else { lat }, if west { -lon } else { lon }));
+            }
+        }
+        Some(exif)
+    }
+
+    // JPEG with the GPS IFD of the point, None without EXIF or when the segment gets too large
+    pub fn write_gps(jpeg: &[u8], p: &TrackPoint) -> Option<Vec<u8>> {
+        let (start, end) = find_exif(jpeg)?;
+        let tiff = Tiff::new(jpeg.get(start + 10..end)?)?;
+        let ifd0_at = tiff.u32(4)? as usize;
+        let ifd0 = tiff.entries(ifd0_at)?;
+        let next_ifd = tiff.u32(ifd0_at + 2 + ifd0.len() * 12)?;
+        let mut out = tiff.data.to_vec();
+
+        // Over the old GPS IFD when the new one fits
+        if let Some((at, size)) = gps_slot(&tiff, ifd0_at, &ifd0, next_ifd) {
+            let block = gps_block(&tiff, p, at);
+            if block.len() <= size {
+                out[at..at + block.len()].copy_from_slice(&block);
+                out[at + block.len()..at + size].fill(0);
+                return exif_segment(jpeg, start, end, out);
+            }
+        }
+
+        if out.len() % 2 == 1 {
+            out.push(0);
+        }
+        let gps_at = out.len();
+        out.extend(gps_block(&tiff, p, gps_at));
+        if out.len() % 2 == 1 {
+            out.push(0);
+        }
+
+        // New IFD0 with the GPS pointer, the other entries are copied as they are
+        let ifd0_new = out.len();
+        let mut entries: Vec<Vec<u8>> = ifd0.iter().filter(|e| e.tag != TAG_GPS_IFD)
+            .map(|e| tiff.data[e.at..e.at + 12].to_vec()).collect();
+        let mut pointer = Vec::new();
+        tiff.put_u16(&mut pointer, TAG_GPS_IFD);
+        tiff.put_u16(&mut pointer, LONG);
+        tiff.put_u32(&mut pointer, 1);
+        tiff.put_u32(&mut pointer, gps_at as u32);
+        entries.push(pointer);
+        entries.sort_by_key(|e| Tiff {data: e, little: tiff.little}.u16(0));
+        tiff.put_u16(&mut out, entries.len() as u16);
+        for e in entries {
+            out.extend(e);
+        }
+        tiff.put_u32(&mut out, next_ifd);
+        let mut header = Vec::new();
+        tiff.put_u32(&mut header, ifd0_new as u32);
+        out[4..8].copy_from_slice(&header);
+        exif_segment(jpeg, start, end, out)
+    }
+
+    // Offset and size of the GPS IFD with its values, None when it has no place of its own
+    // (other IFDs or values inside of it)
+    fn gps_slot(tiff: &Tiff, ifd0_at: usize, ifd0: &[Entry], next_ifd: u32) -> Option<(usize, usize)> {
+        let pointer = ifd0.iter().find(|e| e.tag == TAG_GPS_IFD)?;
+        let at = tiff.u32(pointer.at + 8)? as usize;
+        let entries = tiff.entries(at)?;
+        let mut end = at + 2 + entries.len() * 12 + 4;
+        for e in entries.iter().filter(|e| value_size(e) > 4) {
+            let value = tiff.value_at(e)?;
+            if value < at {
+                return None;
+            }
+            end = end.max(value + value_size(e));
+        }
+        if end > tiff.data.len() {
+            return None;
+        }
+        let mut others = tiff.offsets(ifd0_at, ifd0);
+        if let Some(exif) = ifd0.iter().find(|e| e.tag == TAG_EXIF_IFD).and_then(|e| tiff.u32(e.at + 8)) {
+            others.extend(tiff.offsets(exif as usize, &tiff.entries(exif as usize)?));
+        }
+        if next_ifd != 0 {
+            others.push(next_ifd as usize);
+        }
+        if others.iter().any(|o| (at..end).contains(o)) {
+            return None;
+        }
+        Some((at, end - at))
+    }
+
+    // GPS IFD followed by its values, to be placed at the offset
+    fn gps_block(tiff: &Tiff, p: &TrackPoint, at: usize) -> Vec<u8> {
+        let dms = |v: f64| {
+            let v = v.abs();
+            let (d, m) = (v.floor(), (v * 60f64).floor() % 60f64);
+            let s = v * 3600f64 - d * 3600f64 - m * 60f64;
+            vec![(d as u32, 1), (m as u32, 1), ((s * 1000f64).round() as u32, 1000)]
+        };
+        let rational = |v: Vec<(u32, u32)>| {
+            let mut b = Vec::new();
+            for (n, d) in v {
+                tiff.put_u32(&mut b, n);
+                tiff.put_u32(&mut b, d);
+            }
+            b
+        };
+        // (tag, kind, count, value bytes)
+        let l = &p.location;
+        let mut gps: Vec<(u16, u16, u32, Vec<u8>)> = vec![
+            (0, BYTE, 4, vec![2, 3, 0, 0]),
+            (1, ASCII, 2, vec![if l.lat < 0f64 { b'S' } else { b'N' }, 0]),
+            (2, RATIONAL, 3, rational(dms(l.lat))),
+            (3, ASCII, 2, vec![if l.lon < 0f64 { b'W' } else { b'E' }, 0]),
+            (4, RATIONAL, 3, rational(dms(l.lon))),
+        ];
+        if !p.altitude.is_nan() {
+            gps.push((5, BYTE, 1, vec![if p.altitude < 0f64 { 1 } else { 0 }]));
+            gps.push((6, RATIONAL, 1, rational(vec![((p.altitude.abs() * 100f64).round() as u32, 100)])));
+        }
+        if p.has_time() {
+            let t = p.time;
+            gps.push((7, RATIONAL, 3, rational(vec![(t.hour(), 1), (t.minute(), 1), (t.second(), 1)])));
+            let mut date = format!("{:04}:{:02}:{:02}", t.year(), t.month(), t.day()).into_bytes();
+            date.push(0);
+            gps.push((0x1D, ASCII, 11, date));
+        }
+
+        let mut block = Vec::new();
+        let mut data_at = at + 2 + gps.len() * 12 + 4;
+        let mut data: Vec<u8> = Vec::new();
+        tiff.put_u16(&mut block, gps.len() as u16);
+        for (tag, kind, count, value) in &gps {
+            tiff.put_u16(&mut block, *tag);
+            tiff.put_u16(&mut block, *kind);
+            tiff.put_u32(&mut block, *count);
+            if value.len() <= 4 {
+                let mut inline = value.clone();
+                inline.resize(4, 0);
+                block.extend(inline);
+            } else {
+                tiff.put_u32(&mut block, data_at as u32);
+                data.extend(value);
+                data_at += value.len();
+            }
+        }
+        tiff.put_u32(&mut block, 0);
+        block.extend(data);
+        block
+    }
+
+    // The JPEG with the TIFF block in place of the old Exif segment, None over 64 KiB
+    fn exif_segment(jpeg: &[u8], start: usize, end: usize, tiff: Vec<u8>) -> Option<Vec<u8>> {
+        let length = 2 + EXIF_HEADER.len() + tiff.len();
+        if length > 0xFFFF {
+            return None;
+        }
+        let mut out = Vec::with_capacity(jpeg.len() + length);
+        out.extend(&jpeg[..start]);
+        out.extend([0xFF, 0xE1]);
+        out.extend((length as u16).to_be_bytes());
+        out.extend(EXIF_HEADER);
+        out.extend(tiff);
+        out.extend(&jpeg[end..]);
+        Some(out)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use chrono::TimeZone;
+        use crate::gpx::gpx::{TrackRoute, TrackSegment};
+
+        // JPEG with IFD0 (DateTime, Exif IFD pointer) and Exif IFD (DateTimeOriginal)
+        fn jpeg(little: bool, time: &str) -> Vec<u8> {
+            let t = Tiff {data: &[], little};
+            let mut tiff = Vec::new();
+            tiff.extend(if little { b"II" } else { b"MM" });
+            t.put_u16(&mut tiff, 42);
+            t.put_u32(&mut tiff, 8);
+            // IFD0 at 8: 2 entries, Exif IFD at 38, DateTime at 56
+            t.put_u16(&mut tiff, 2);
+            for (tag, kind, count, value) in [(TAG_DATE_TIME, ASCII, 20, 56), (TAG_EXIF_IFD, LONG, 1, 38)] {
+                t.put_u16(&mut tiff, tag);
+                t.put_u16(&mut tiff, kind);
+                t.put_u32(&mut tiff, count);
+                t.put_u32(&mut tiff, value);
+            }
+            t.put_u32(&mut tiff, 0);
+            // Exif IFD at 38: DateTimeOriginal at 76
+            t.put_u16(&mut tiff, 1);
+            t.put_u16(&mut tiff, TAG_DATE_TIME_ORIGINAL);
+            t.put_u16(&mut tiff, ASCII);
+            t.put_u32(&mut tiff, 20);
+            t.put_u32(&mut tiff, 76);
+            t.put_u32(&mut tiff, 0);
+            tiff.extend(b"2000:01:01 00:00:00\0");
+            tiff.extend(format!("{time}\0").as_bytes());
+
+            let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1];
+            jpeg.extend(((2 + 6 + tiff.len()) as u16).to_be_bytes());
+            jpeg.extend(EXIF_HEADER);
+            jpeg.extend(tiff);
+            jpeg.extend([0xFF, 0xDA, 0x00, 0x02, 0x12, 0x34, 0xFF, 0xD9]);
+            jpeg
+        }
+
+        #[test]
+        fn geotag() {
+            // 10 minutes toward north, camera in JST running 30 s ahead
+            let start = Utc.with_ymd_and_hms(2023, 8, 1, 6, 0, 0).unwrap();
+            let mut segment = TrackSegment::new();
+            for i in 0..=10 {
+                let mut p = TrackPoint::new(35.0 + i as f64 * 0.001, 139.0);
+                p.time = start + Duration::minutes(i);
+                p.altitude = 1000f64 + i as f64;
+                segment.add_point(p);
+            }
+            let mut route = TrackRoute::new();
+            route.add_segment(&segment);
+            let mut track = Track::new();
+            track.routes.push(route);
+
+            let dir = std::env::temp_dir().join("geotool_geotag");
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("a.jpg"), jpeg(true, "2023:08:01 15:02:30")).unwrap();
+            fs::write(dir.join("b.JPG"), jpeg(false, "2023:08:01 15:05:00")).unwrap();
+            fs::write(dir.join("c.jpg"), jpeg(true, "2023:08:01 18:00:00")).unwrap();
+            fs::write(dir.join("notes.txt"), "not a photo").unwrap();
+
+            let mut tagger = Geotagger::new(FixedOffset::east_opt(9 * 3600).unwrap());
+            tagger.set_clock_offset(Duration::seconds(30));
+            let photos = tagger.locate(&track, &dir).unwrap();
+            assert_eq!(photos.len(), 3);
+            assert_eq!(photos[0].time, start + Duration::seconds(150));
+            let a = photos[0].point.as_ref().unwrap();
+            assert!((a.location.lat - 35.002).abs() < 1e-9);
+            assert!((photos[1].point.as_ref().unwrap().location.lat - 35.0045).abs() < 1e-9);
+            assert!(photos[2].point.is_none());
+
+            let points = tagger.waypoints(&photos);
+            assert_eq!(points.len(), 2);
+            assert_eq!(points[1].name, "b.JPG");
+            assert!(points[1].link.ends_with("b.JPG"));
+
+            let report = tagger.write_exif(&photos);
+            assert_eq!(report.written, 2);
+            assert!(report.failed.is_empty());
+            for (name, lat) in [("a.jpg", 35.002), ("b.JPG", 35.0045)] {
+                let bytes = fs::read(dir.join(name)).unwrap();
+                let exif = read_exif(&bytes).unwrap();
+                let gps = exif.gps.unwrap();
+                assert!((gps.lat - lat).abs() < 1e-6 && (gps.lon - 139.0).abs() < 1e-6);
+                // The rest of the file and the EXIF time are kept
+                assert!(bytes.ends_with(&[0xFF, 0xDA, 0x00, 0x02, 0x12, 0x34, 0xFF, 0xD9]));
+                assert!(exif.date_time_original.is_some());
+            }
+            // Tagging again overwrites the GPS IFD in its place, the file does not grow
+            let size = fs::metadata(dir.join("a.jpg")).unwrap().len();
+            let mut moved = photos.clone();
+            moved[0].point.as_mut().unwrap().location.lat = 35.003;
+            assert_eq!(tagger.write_exif(&moved).written, 2);
+            assert_eq!(fs::metadata(dir.join("a.jpg")).unwrap().len(), size);
+            assert!((read_exif(&fs::read(dir.join("a.jpg")).unwrap()).unwrap().gps.unwrap().lat - 35.003).abs() < 1e-6);
+            assert!(read_exif(&fs::read(dir.join("c.jpg")).unwrap()).unwrap().gps.is_none());
+
+            // A photo without EXIF fails alone and is left as it was
+            let broken = [0xFF, 0xD8, 0xFF, 0xD9];
+            fs::write(dir.join("0.jpg"), broken).unwrap();
+            let mut with_broken = vec![Photo {path: dir.join("0.jpg"), time: start, point: photos[0].point.clone()}];
+            with_broken.extend(photos.iter().cloned());
+            let report = tagger.write_exif(&with_broken);
+            assert_eq!(report.written, 2);
+            assert_eq!(report.failed.len(), 1);
+            assert_eq!(report.failed[0].0, dir.join("0.jpg"));
+            assert_eq!(fs::read(dir.join("0.jpg")).unwrap(), broken);
+            // No temporary file is left
+            assert_eq!(fs::read_dir(&dir).unwrap().count(), 5);
+        }
+    }
+}
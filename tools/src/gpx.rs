@@ -5,8 +5,11 @@ pub mod gpx {
     use std::fmt;
     use std::fmt::Formatter;
     use std::ffi::OsString;
-    use crate::geometry::geometry_core::{Geometry, LatLon, Area};
+    use crate::geometry::geometry_core::{Geometry, LatLon, Area, DistanceModel, TileCoverage};
+    use crate::geoid::geoid::{GeoidGrid, VerticalDatum};
+    use std::ops::RangeInclusive;
     use chrono::prelude::{DateTime, Utc};
+    use chrono::Duration;
     use num::complex::ComplexFloat;
     use bitfield::BitRangeMut;
     use bitfield::{bitfield_bitrange, bitfield_debug, bitfield_fields};
@@ -129,6 +132,7 @@ pub mod gpx {
         pub point_type: Vec::<PointType>,
         pub comment: String,
         pub name: String,
+        pub link: String,           // href of GPX link, a photo or a web page
     }
 
     impl TrackPoint {
@@ -153,6 +157,7 @@ pub mod gpx {
                 point_type: vec![PointType::None],
                 comment: String::new(),
                 name: String::new(),
+                link: String::new(),
             }
         }
 
@@ -200,6 +205,10 @@ pub mod gpx {
             return list.clone();
         }
 
+        pub fn has_time(&self) -> bool {
+            is_known_time(&self.time)
+        }
+
         pub fn has_extension(&self) -> bool {
             if (!self.heading.is_nan()) {return true}
             if (!self.pressure.is_nan()) {return true}
@@ -248,6 +257,16 @@ pub mod gpx {
         }
     }
 
+    // Unknown times are DateTime::<Utc>::MIN_UTC
+    pub fn is_known_time(t: &DateTime<Utc>) -> bool {
+        *t != DateTime::<Utc>::MIN_UTC
+    }
+
+    // Duration in seconds with the milliseconds
+    pub fn seconds(d: &Duration) -> f64 {
+        d.num_milliseconds() as f64 / 1000f64
+    }
+
     #[derive(Clone, Debug)]
     pub struct PointAttr {
         pub point: TrackPoint,
@@ -256,6 +275,59 @@ pub mod gpx {
         direction: f64,
     }
 
+    impl PointAttr {
+        pub fn geometry(&self) -> &Geometry {
+            &self.geometry
+        }
+
+        // Distance from the previous point (m)
+        pub fn distance(&self) -> f64 {
+            self.distance
+        }
+
+        // Bearing of travel at this point, degrees clockwise from north
+        pub fn direction(&self) -> f64 {
+            self.direction
+        }
+    }
+
+    // Closest location on a track line
+    //   index: the leg from points[index] to points[index + 1], fraction: 0.0 ~ 1.0 along the leg
+    //   distance: from the queried location (m), along: from the first point along the track (m)
+    //   segment_fraction: along / distance of the whole segment
+    #[derive(Clone, Copy, Debug)]
+    pub struct ClosestPoint {
+        pub location: LatLon,
+        pub index: usize,
+        pub fraction: f64,
+        pub distance: f64,
+        pub along: f64,
+        pub segment_fraction: f64,
+    }
+
+    // Why convert_altitude did not change the altitudes
+    //   UnknownDatum: the current or the requested vertical datum is unknown
+    //   OutsideGeoid: the points with altitude outside of the geoid grid, nothing is converted
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum AltitudeError<I> {
+        UnknownDatum,
+        OutsideGeoid(Vec<I>),
+    }
+
+    // A point in a route, and in a track
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct SegmentPoint {
+        pub segment: usize,
+        pub point: usize,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct RoutePoint {
+        pub route: usize,
+        pub segment: usize,
+        pub point: usize,
+    }
+
     #[derive(Clone, Debug)]
     pub struct TrackSegment {
         pub points: Vec<PointAttr>,
@@ -265,6 +337,8 @@ pub mod gpx {
         lowest: f64,
         distance: f64,
         area: Area,
+        model: DistanceModel,
+        vertical_datum: VerticalDatum,
     }
 
     impl TrackSegment {
@@ -277,32 +351,82 @@ pub mod gpx {
                 lowest: f64::NAN,
                 distance: 0f64,
                 area:Area::invalid(),
+                model: DistanceModel::Ellipsoid,
+                vertical_datum: VerticalDatum::Unknown,
             }
         }
 
-        pub fn add_point(self: &mut Self, p: TrackPoint) {
-            if (self.highest < p.altitude) {
-                self.highest = p.altitude
+        pub fn distance_model(&self) -> DistanceModel {
+            self.model
+        }
+
+        // Change how distances between points are measured and recalculate them
+        pub fn set_distance_model(self: &mut Self, model: DistanceModel) {
+            self.model = model;
+            self.reparse();
+        }
+
+        pub fn vertical_datum(&self) -> VerticalDatum {
+            self.vertical_datum
+        }
+
+        // Declare which vertical datum the altitudes are in, the altitudes are not changed
+        pub fn set_vertical_datum(self: &mut Self, datum: VerticalDatum) {
+            self.vertical_datum = datum;
+        }
+
+        // Convert the altitudes to the vertical datum with the geoid grid, all of them or none
+        pub fn convert_altitude(self: &mut Self, geoid: &GeoidGrid, to: VerticalDatum) -> Result<(), AltitudeError<usize>> {
+            if self.vertical_datum == VerticalDatum::Unknown || to == VerticalDatum::Unknown {
+                return Err(AltitudeError::UnknownDatum);
+            }
+            if self.vertical_datum == to {
+                return Ok(());
             }
-            if (self.lowest > p.altitude) {
-                self.lowest = p.altitude
+            let outside = self.outside_geoid(geoid);
+            if !outside.is_empty() {
+                return Err(AltitudeError::OutsideGeoid(outside));
+            }
+            for p in self.points.iter_mut() {
+                if let Some(h) = geoid.convert(&p.point.location, p.point.altitude, self.vertical_datum, to) {
+                    p.point.altitude = h;
+                    p.geometry.alt = h;
+                }
             }
+            self.vertical_datum = to;
+            self.reparse();
+            Ok(())
+        }
+
+        // Indices of the points with altitude outside of the geoid grid
+        pub fn outside_geoid(&self, geoid: &GeoidGrid) -> Vec<usize> {
+            (0..self.points.len())
+                .filter(|i| !self.points[*i].point.altitude.is_nan() && geoid.undulation(&self.points[*i].point.location).is_none())
+                .collect()
+        }
+
+        pub fn add_point(self: &mut Self, p: TrackPoint) {
+            // f64::max and min ignore NaN, the first known altitude is taken
+            self.highest = self.highest.max(p.altitude);
+            self.lowest = self.lowest.min(p.altitude);
             let g = Geometry{location: p.location.clone(), alt: p.altitude};
-            let d = if self.points.len() == 0 {
-                0f64
-            } else {
-                self.points[self.points.len() - 1].geometry.distance(&g)
-            };
-            self.distance += d;
             self.area.enter(&p.location);
-            let point = PointAttr{point: p.clone(), geometry: g, distance: d, direction: 0f64};
+            let point = PointAttr{point: p.clone(), geometry: g, distance: 0f64, direction: f64::NAN};
             self.points.push(point);
+            let i = self.points.len() - 1;
+            self.update_leg(i);
+            self.distance += self.points[i].distance;
         }
 
         pub fn append(self: &mut Self, seg: &mut TrackSegment) {
-            if (self.lowest > seg.lowest) {self.lowest = seg.lowest}
-            if (self.highest < seg.highest) {self.highest = seg.highest}
+            self.lowest = self.lowest.min(seg.lowest);
+            self.highest = self.highest.max(seg.highest);
+            let i = self.points.len();
             self.points.append(&mut seg.points);
+            self.update_leg(i);
+            if i < self.points.len() {
+                self.distance += seg.distance + self.points[i].distance;
+            }
             self.area.add(&seg.area);
         }
 
@@ -320,45 +444,51 @@ pub mod gpx {
         }
 
         pub fn cut_in(self: &mut Self, i: usize, segment: &mut TrackSegment) {
-            if (self.highest < segment.highest) {
-                self.highest = segment.highest
-            }
-            if (self.lowest > segment.lowest) {
-                self.lowest = self.lowest
-            }
-            if (self.points.len() == 0) {
-                self.append(segment);
-                self.distance = segment.distance
-            } else if (i == 0) {
-                let l = self.points.len();
-                self.points[l - 1].distance
-                    = self.points[l - 1].geometry.distance(&segment.points[0].geometry);
-                self.distance += segment.distance + self.points[l - 1].distance;
-
-                self.points[l - 1].direction
-                    = self.points[l - 1].point.location.direction(&segment.points[0].point.location);
-
-                let mut a = segment.points.clone();
-                a.append(&mut self.points);
-                self.points = a;
-            } else {
-                segment.points[0].distance = segment.points[0].geometry.distance(&self.points[i - 1].geometry);
-                self.distance -= self.points[i].distance;
-                self.points[i].distance
-                    = self.points[i].geometry.distance(&segment.points[segment.points.len() - 1].geometry);
-                let mut b = self.points.split_off(i);
-                self.distance += self.points[i].distance;
-
-                segment.points[0].direction
-                    = segment.points[0].point.location.direction(&self.points[i - 1].point.location);
-                self.points[i].direction
-                    = self.points[i].point.location.distance(&segment.points[segment.points.len() - 1].point.location);
-                self.points.append(&mut segment.points);
-                self.points.append(&mut b);
+            self.highest = self.highest.max(segment.highest);
+            self.lowest = self.lowest.min(segment.lowest);
+            // Insert segment before the point i, only the legs at both ends of segment change
+            let i = i.min(self.points.len());
+            let n = segment.points.len();
+            if n == 0 {
+                return;
+            }
+            let old = if i < self.points.len() { self.points[i].distance } else { 0f64 };
+            let mut b = self.points.split_off(i);
+            self.points.append(&mut segment.points);
+            self.points.append(&mut b);
+            self.update_leg(i);
+            self.update_leg(i + n);
+            self.distance += segment.distance - old + self.points[i].distance;
+            if i + n < self.points.len() {
+                self.distance += self.points[i + n].distance;
             }
             self.area.add(&segment.area);
         }
 
+        // Update distance and direction of the point i from the previous point.
+        // The first point has no previous one, its direction is toward the next point.
+        fn update_leg(self: &mut Self, i: usize) {
+            if i >= self.points.len() {
+                return;
+            }
+            if i == 0 {
+                self.points[0].distance = 0f64;
+                self.points[0].direction = if self.points.len() > 1 {
+                    self.points[0].point.location.initial_bearing(&self.points[1].point.location)
+                } else {
+                    f64::NAN
+                };
+                return;
+            }
+            let prev = self.points[i - 1].geometry.clone();
+            let p = &mut self.points[i];
+            p.distance = prev.distance_by(&p.geometry, self.model);
+            p.direction = prev.location.final_bearing(&p.geometry.location);
+            if i == 1 {
+                self.update_leg(0);
+            }
+        }
+
         /// Follow and update point's following attributes
         ///     distance
         ///     direction of compass
@@ -369,15 +499,12 @@ pub mod gpx {
             self.distance = 0f64;
             self.highest = f64::NAN;
             self.lowest = f64::NAN;
-            self.area = Area::invalid();
 
             // Reparse segment
             for i in 0..self.points.len() {
-                // These 2 declarations are used in only else clause but borrow checker point of view, need to declare them here...
-                let prev_g = self.points[i - 1].geometry.clone();
-                let prev_l = self.points[i - 1].point.location.clone();
+                self.update_leg(i);
 
-                let mut p = &mut self.points[usize::from(i)];
+                let p = &self.points[i];
                 if !f64::is_nan(p.point.altitude) && f64::is_nan(self.highest) {
                     self.highest = p.point.altitude
                 } else if !f64::is_nan(p.point.altitude) && (self.highest <  p.point.altitude) {
@@ -385,80 +512,158 @@ pub mod gpx {
                 }
                 if !f64::is_nan(p.point.altitude) && f64::is_nan(self.lowest) {
                     self.lowest = p.point.altitude
-                } else if f64::is_nan(p.point.altitude) && (self.lowest >  p.point.altitude) {
+                } else if !f64::is_nan(p.point.altitude) && (self.lowest >  p.point.altitude) {
                     self.lowest = p.point.altitude
                 }
-                if !f64::is_nan(p.distance) {
-                    self.distance += p.distance
-                } else if (i != 0) {
-                    p.distance = p.geometry.distance(&prev_g);
-                    self.distance += p.distance;
-
-                    p.direction = p.point.location.direction(&prev_l);
-                }
-                self.area.enter(&p.point.location);
+                self.distance += p.distance;
             }
+            self.update_area();
         }
 
         pub fn insert_at(self: &mut Self, p: TrackPoint, i: usize) {
             if (self.points.len() < i) {
                 panic!("{i} is bigger than currently segment has")
             }
-            if (self.highest < p.altitude) {
-                self.highest = p.altitude
-            }
-            if (self.lowest > p.altitude) {
-                self.lowest = p.altitude
-            }
-            let mut pt = PointAttr{point: p.clone(), distance: 0f64, geometry: Geometry{location: p.location.clone(), alt: p.altitude.clone()}, direction:0f64};
-            if (self.points.len() != 0) {
-                // Recalculate direction and distance
-                if (i != (self.points.len() - 1)) {
-                    // Not last
-                    let mut n = &mut self.points[i + 1];
-                    n.direction = n.geometry.location.distance(&p.location);
-                    n.distance = n.geometry.distance(&pt.geometry);
-                }
-                if (i != 0) {
-                    let n = &self.points[i - 1];
-                    pt.direction = n.geometry.location.distance(&p.location);
-                    pt.distance = n.geometry.distance(&pt.geometry);
-
-                }
-            }
+            // f64::max and min ignore NaN, the first known altitude is taken
+            self.highest = self.highest.max(p.altitude);
+            self.lowest = self.lowest.min(p.altitude);
+            let pt = PointAttr{point: p.clone(), distance: 0f64, geometry: Geometry{location: p.location.clone(), alt: p.altitude.clone()}, direction: f64::NAN};
+            let old = if i < self.points.len() { self.points[i].distance } else { 0f64 };
             self.area.enter(&p.location);
             self.points.insert(i, pt);
+
+            // Recalculate direction and distance
+            self.update_leg(i);
+            self.update_leg(i + 1);
+            self.distance += self.points[i].distance - old;
+            if i + 1 < self.points.len() {
+                self.distance += self.points[i + 1].distance;
+            }
         }
 
         pub fn remove_at(self: &mut Self, p: TrackPoint, i: usize) {
-            if (self.points.len() < i) {
+            if (self.points.len() <= i) {
                 panic!("{i} is bigger than currently segment has")
             }
 
-            // Distance re-calculation
-            if (self.points.len() > 1) {
+            // Distance and direction re-calculation
+            self.distance -= self.points[i].distance;
+            if i + 1 < self.points.len() {
                 self.distance -= self.points[i + 1].distance;
-                if (i == 0) {
-                    self.points[i + 1].distance = 0f64;
-                } else if (self.points.len() > 2) && (i != (self.points.len() - 1)) {
-                    self.points[i + 1].distance = self.points[i + 1].geometry.distance(&self.points[i - 1].geometry);
-                }
             }
 
-            self.points.remove(i);
+            let removed = self.points.remove(i).point.location;
+
+            self.update_leg(i);
+            if i < self.points.len() {
+                self.distance += self.points[i].distance;
+            }
+            if i == self.points.len() && i > 0 {
+                // Removed the last point, direction of the first point may have lost its next point
+                self.update_leg(i - 1);
+            }
 
             if (p.altitude <= self.lowest) || (p.altitude >= self.highest) {
                 // Recalc
                 self.update_minmax();
             }
-            if (p.location.lat > self.area.north_west.lat) {}
+            if (removed.lat == self.area.north()) || (removed.lat == self.area.south())
+                || (removed.lon == self.area.west()) || (removed.lon == self.area.east()) {
+                // The point was on the edge, the area may shrink
+                self.update_area();
+            }
         }
 
-        fn update_minmax(self: &mut Self) {
+        fn update_area(self: &mut Self) {
+            self.area = Area::invalid();
+            for p in &self.points {
+                self.area.enter(&p.point.location);
+            }
+        }
+
+        // Total distance (m)
+        pub fn distance(&self) -> f64 {
+            self.distance
+        }
 
+        // Highest and lowest altitude (m), NaN when no point has altitude
+        pub fn highest(&self) -> f64 {
+            self.highest
+        }
+
+        pub fn lowest(&self) -> f64 {
+            self.lowest
+        }
+
+        pub fn area(&self) -> &Area {
+            &self.area
+        }
+
+        // Tiles within the buffer distance (m) of the track
+        pub fn tile_corridor(&self, buffer: f64, zoom: RangeInclusive<u32>) -> TileCoverage {
+            let line: Vec<LatLon> = self.points.iter().map(|p| p.point.location).collect();
+            TileCoverage::from_corridor(&line, buffer, zoom)
+        }
+
+        // Closest location on the track line (not only the vertices)
+        pub fn closest_point(&self, location: &LatLon) -> Option<ClosestPoint> {
+            if self.points.is_empty() {
+                return None;
+            }
+            let first = &self.points[0].point.location;
+            let mut best = ClosestPoint{location: *first, index: 0, fraction: 0f64,
+                distance: location.great_circle_distance(first), along: 0f64, segment_fraction: 0f64};
+            let mut along = 0f64;
+            for i in 1..self.points.len() {
+                let start = &self.points[i - 1].point.location;
+                let end = &self.points[i].point.location;
+                let leg = self.points[i].distance;
+                let (p, fraction) = location.closest_on_arc(start, end);
+                let d = location.great_circle_distance(&p);
+                if d < best.distance {
+                    best = ClosestPoint{location: p, index: i - 1, fraction, distance: d,
+                        along: along + leg * fraction, segment_fraction: 0f64};
+                }
+                along += leg;
+            }
+            if best.fraction >= 1f64 && best.index + 1 < self.points.len() {
+                // Report the end of the leg as the vertex itself
+                best.index += 1;
+                best.fraction = 0f64;
+            }
+            if self.distance > 0f64 {
+                best.segment_fraction = (best.along / self.distance).clamp(0f64, 1f64);
+            }
+            Some(best)
+        }
+
+        // Distance (m) from the location to the track line, NaN for an empty segment
+        pub fn distance_from(&self, location: &LatLon) -> f64 {
+            self.closest_point(location).map_or(f64::NAN, |c| c.distance)
+        }
+
+        // Signed distance (m) from the closest leg of the track line, positive on the right side of travel
+        pub fn cross_track_distance(&self, location: &LatLon) -> f64 {
+            let c = match self.closest_point(location) {
+                Some(c) => { c }
+                None => { return f64::NAN; }
+            };
+            let (start, end) = if c.index + 1 < self.points.len() {
+                (&self.points[c.index].point.location, &self.points[c.index + 1].point.location)
+            } else if c.index > 0 {
+                (&self.points[c.index - 1].point.location, &self.points[c.index].point.location)
+            } else {
+                return c.distance;
+            };
+            if location.cross_track_distance(start, end) < 0f64 { -c.distance } else { c.distance }
+        }
+
+        fn update_minmax(self: &mut Self) {
+            self.highest = f64::NAN;
+            self.lowest = f64::NAN;
             for p in &self.points {
-                if (p.point.altitude < self.lowest) {self.lowest = p.point.altitude;}
-                if (p.point.altitude > self.highest) {self.highest = p.point.altitude;}
+                self.highest = self.highest.max(p.point.altitude);
+                self.lowest = self.lowest.min(p.point.altitude);
             }
         }
     }
@@ -492,22 +697,60 @@ pub mod gpx {
             Self {segments: Vec::new(),
                 name: String::new(),
                 comment: String::new(),
-                highest: 0f64,
-                lowest: 0f64,
+                highest: f64::NAN,
+                lowest: f64::NAN,
                 distance: 0f64,
             }
         }
 
         pub fn add_segment(self: &mut Self, segment: &TrackSegment) {
-            if (segment.lowest < self.lowest) {
-                self.lowest = segment.lowest;
+            self.lowest = self.lowest.min(segment.lowest);
+            self.highest = self.highest.max(segment.highest);
+            self.segments.push(segment.clone());
+            self.distance += segment.distance;
+        }
+
+        // Convert the altitudes of the segments with a known datum, all of them or none
+        pub fn convert_altitude(self: &mut Self, geoid: &GeoidGrid, to: VerticalDatum) -> Result<(), AltitudeError<SegmentPoint>> {
+            let known: Vec<usize> = (0..self.segments.len())
+                .filter(|i| self.segments[*i].vertical_datum() != VerticalDatum::Unknown && to != VerticalDatum::Unknown)
+                .collect();
+            if known.is_empty() {
+                return Err(AltitudeError::UnknownDatum);
             }
+            let outside: Vec<SegmentPoint> = known.iter()
+                .filter(|i| self.segments[**i].vertical_datum() != to)
+                .flat_map(|i| self.segments[*i].outside_geoid(geoid).into_iter().map(move |p| SegmentPoint {segment: *i, point: p}))
+                .collect();
+            if !outside.is_empty() {
+                return Err(AltitudeError::OutsideGeoid(outside));
+            }
+            for i in known {
+                let _ = self.segments[i].convert_altitude(geoid, to);
+            }
+            self.highest = self.segments.iter().map(|s| s.highest).fold(f64::NAN, f64::max);
+            self.lowest = self.segments.iter().map(|s| s.lowest).fold(f64::NAN, f64::min);
+            Ok(())
+        }
+
+        pub fn distance(&self) -> f64 {
+            self.distance
+        }
+
+        pub fn highest(&self) -> f64 {
+            self.highest
+        }
+
+        pub fn lowest(&self) -> f64 {
+            self.lowest
+        }
 
-            if (segment.highest < self.highest) {
-                self.highest = segment.highest;
+        pub fn area(&self) -> Area {
+            let mut area = Area::invalid();
+            for s in &self.segments {
+                area.add(&s.area);
             }
-            self.segments.push(segment.clone());
-            self.distance += segment.distance;
+            area
         }
     }
 
@@ -540,6 +783,7 @@ pub mod gpx {
     #[derive(Clone)]
     pub struct Track {
         pub routes: Vec<TrackRoute>,
+        pub waypoints: Vec<TrackPoint>,     // wpt, not a part of the routes
         pub name: String,
         pub comment: String,
     }
@@ -548,10 +792,51 @@ pub mod gpx {
         pub fn new() -> Self {
             Self {
                 routes: Vec::new(),
+                waypoints: Vec::new(),
                 name: String::new(),
                 comment: String::new(),
             }
         }
+
+        pub fn add_waypoint(self: &mut Self, point: TrackPoint) {
+            self.waypoints.push(point);
+        }
+
+        // Declare the vertical datum of all the segments
+        pub fn set_vertical_datum(self: &mut Self, datum: VerticalDatum) {
+            for r in self.routes.iter_mut() {
+                for s in r.segments.iter_mut() {
+                    s.set_vertical_datum(datum);
+                }
+            }
+        }
+
+        // Convert the altitudes of the segments with a known datum in all the routes, all of them or none
+        pub fn convert_altitude(self: &mut Self, geoid: &GeoidGrid, to: VerticalDatum) -> Result<(), AltitudeError<RoutePoint>> {
+            let mut outside = Vec::new();
+            let mut known = false;
+            for (r, route) in self.routes.iter().enumerate() {
+                for (i, s) in route.segments.iter().enumerate() {
+                    if s.vertical_datum() == VerticalDatum::Unknown || to == VerticalDatum::Unknown {
+                        continue;
+                    }
+                    known = true;
+                    if s.vertical_datum() != to {
+                        outside.extend(s.outside_geoid(geoid).into_iter().map(|p| RoutePoint {route: r, segment: i, point: p}));
+                    }
+                }
+            }
+            if !known {
+                return Err(AltitudeError::UnknownDatum);
+            }
+            if !outside.is_empty() {
+                return Err(AltitudeError::OutsideGeoid(outside));
+            }
+            for r in self.routes.iter_mut() {
+                let _ = r.convert_altitude(geoid, to);
+            }
+            Ok(())
+        }
     }
 
     impl fmt::Debug for crate::gpx::gpx::Track {
@@ -575,5 +860,95 @@ pub mod gpx {
         }
     }
 
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn closest_point() {
+            let mut segment = TrackSegment::new();
+            segment.add_point(TrackPoint::new(35.0, 139.0));
+            segment.add_point(TrackPoint::new(35.0, 139.01));
+            segment.add_point(TrackPoint::new(35.01, 139.01));
+
+            // Beside the middle of the first leg, north is on the left going east
+            let c = segment.closest_point(&LatLon::new(35.001, 139.005)).unwrap();
+            assert_eq!(c.index, 0);
+            assert!((c.fraction - 0.5).abs() < 1e-3);
+            assert!((c.distance - 111.2).abs() < 0.5);
+            assert!((c.along - segment.points[1].distance() * c.fraction).abs() < 1e-6);
+            assert!(segment.cross_track_distance(&LatLon::new(35.001, 139.005)) < 0f64);
+            assert!(segment.cross_track_distance(&LatLon::new(34.999, 139.005)) > 0f64);
+
+            // Beyond the ends
+            let c = segment.closest_point(&LatLon::new(35.0, 138.99)).unwrap();
+            assert_eq!((c.index, c.fraction, c.along), (0, 0f64, 0f64));
+            let c = segment.closest_point(&LatLon::new(35.02, 139.01)).unwrap();
+            assert_eq!((c.index, c.fraction), (2, 0f64));
+            assert_eq!(c.segment_fraction, 1f64);
+            // At the corner
+            let c = segment.closest_point(&LatLon::new(34.999, 139.011)).unwrap();
+            assert_eq!((c.index, c.fraction), (1, 0f64));
+
+            assert!(TrackSegment::new().distance_from(&LatLon::new(0f64, 0f64)).is_nan());
+        }
+
+        #[test]
+        fn direction() {
+            // North, then east
+            let mut segment = TrackSegment::new();
+            segment.add_point(TrackPoint::new(35.0, 139.0));
+            assert!(segment.points[0].direction().is_nan());
+            segment.add_point(TrackPoint::new(35.01, 139.0));
+            segment.add_point(TrackPoint::new(35.01, 139.01));
+            // The first point heads to the next one
+            assert!(segment.points[0].direction().abs() < 1e-9);
+            assert!(segment.points[1].direction().abs() < 1e-9);
+            let east = LatLon::new(35.01, 139.0).final_bearing(&LatLon::new(35.01, 139.01));
+            assert!((segment.points[2].direction() - east).abs() < 1e-9);
+            assert!((segment.points[2].direction() - 90f64).abs() < 0.01);
+        }
 
-}
\ No newline at end of file
+        #[test]
+        fn convert_altitude() {
+            let text = "35.00000 139.00000 0.100000 0.100000 2 2 1 ver2.1\n40.0 40.0\n40.0 40.0\n";
+            let geoid = GeoidGrid::parse(text.as_bytes()).unwrap();
+            let mut segment = TrackSegment::new();
+            for (lat, alt) in [(35.05, 1040f64), (36.0, 1000f64)] {
+                let mut p = TrackPoint::new(lat, 139.05);
+                p.altitude = alt;
+                segment.add_point(p);
+            }
+            assert_eq!(segment.convert_altitude(&geoid, VerticalDatum::Orthometric), Err(AltitudeError::UnknownDatum));
+            segment.set_vertical_datum(VerticalDatum::Ellipsoidal);
+            // The second point is outside of the grid, nothing is converted
+            assert_eq!(segment.convert_altitude(&geoid, VerticalDatum::Orthometric), Err(AltitudeError::OutsideGeoid(vec![1])));
+            assert_eq!(segment.vertical_datum(), VerticalDatum::Ellipsoidal);
+            assert_eq!(segment.points[0].point.altitude, 1040f64);
+            assert_eq!(segment.points[1].point.altitude, 1000f64);
+
+            let mut route = TrackRoute::new();
+            route.add_segment(&segment);
+            assert_eq!(route.convert_altitude(&geoid, VerticalDatum::Orthometric),
+                       Err(AltitudeError::OutsideGeoid(vec![SegmentPoint {segment: 0, point: 1}])));
+            let mut track = Track::new();
+            track.routes.push(route);
+            assert_eq!(track.convert_altitude(&geoid, VerticalDatum::Orthometric),
+                       Err(AltitudeError::OutsideGeoid(vec![RoutePoint {route: 0, segment: 0, point: 1}])));
+
+            // A point without altitude does not need the grid
+            segment.points[1].point.altitude = f64::NAN;
+            assert_eq!(segment.convert_altitude(&geoid, VerticalDatum::Orthometric), Ok(()));
+            assert_eq!(segment.vertical_datum(), VerticalDatum::Orthometric);
+            assert_eq!(segment.points[0].point.altitude, 1000f64);
+            assert!(segment.points[1].point.altitude.is_nan());
+
+            let mut route = TrackRoute::new();
+            route.add_segment(&segment);
+            let mut track = Track::new();
+            track.routes.push(route);
+            assert_eq!(track.convert_altitude(&geoid, VerticalDatum::Ellipsoidal), Ok(()));
+            assert_eq!(track.routes[0].segments[0].points[0].point.altitude, 1040f64);
+        }
+    }
+}
@@ -9,11 +9,15 @@ pub mod gpx_parser {
     use xml::EventReader;
     use xml::reader::XmlEvent;
     use crate::gpx::gpx::*;
+    use crate::datum::datum::{Datum, DatumTransform};
     use chrono::prelude::Utc;
 
     pub struct GPXParser {
         file: OsString,
         parser: EventReader<BufReader<File>>,
+        datum: Datum,
+        transform: DatumTransform,
+        waypoints: Vec<TrackPoint>,
     }
 
     impl GPXParser {
@@ -21,7 +25,13 @@ pub mod gpx_parser {
         pub fn new(name: &OsString) -> Option<GPXParser> {
             let file = File::open(name).unwrap();
             let file = BufReader::new(file); // Buffering is important for performance
-            Some(GPXParser{file: name.clone(), parser: EventReader::new(file)})
+            Some(GPXParser{file: name.clone(), parser: EventReader::new(file), datum: Datum::Wgs84, transform: DatumTransform::new(), waypoints: Vec::new()})
+        }
+
+        // Declare the datum of the coordinates in the file, points are converted to WGS84 on import
+        pub fn set_datum(self: &mut Self, datum: Datum, transform: DatumTransform) {
+            self.datum = datum;
+            self.transform = transform;
         }
         pub fn open(self: &mut Self) -> Option<Track> {
             let mut route = self.process_gpx();
@@ -29,6 +39,7 @@ pub mod gpx_parser {
             match route {
                 Some(x) => {
                     track.routes = x;
+                    track.waypoints = std::mem::take(&mut self.waypoints);
                     Some(track)
                 },
                 _ => {
@@ -41,9 +52,12 @@ pub mod gpx_parser {
 
             loop {
                 match self.parser.next() {
-                    Ok(XmlEvent::StartElement { name, .. }) => {
+                    Ok(XmlEvent::StartElement { name, attributes, .. }) => {
                         match name.local_name.as_str() {
                             "trk" => { trk.push(self.process_route().unwrap()); }
+                            "wpt" => {
+                                if let Some(p) = self.process_point(attributes) { self.waypoints.push(p); }
+                            }
                             "extensions" => {
                             }
                             "name" => {
@@ -238,18 +252,26 @@ pub mod gpx_parser {
             }
             if (lat.is_nan() || lon.is_nan()) {return None;}
             let mut point = TrackPoint::new(lat, lon);
+            if (self.datum != Datum::Wgs84) {
+                point.location = self.transform.transform(&point.location, self.datum, Datum::Wgs84);
+            }
 
             loop {
                 match self.parser.next() {
-                    Ok(XmlEvent::StartElement { name, .. }) => {
+                    Ok(XmlEvent::StartElement { name, attributes, .. }) => {
                         match name.local_name.as_str() {
                             "extensions" => { self.process_point_extensions(&mut point); }
+                            "link" => {
+                                if let Some(href) = attributes.iter().find(|a| a.name.local_name == "href") {
+                                    point.link = href.value.clone();
+                                }
+                            }
                             _ => { self.apply_point(name.local_name.as_str(), &mut point);}
                         }
                     }
                     Ok(XmlEvent::EndElement { name }) => {
 //                        println!("{:spaces$}-{name}", "", spaces = depth * 2);
-                        if (name.local_name == "trkpt") {break;}
+                        if (name.local_name == "trkpt" || name.local_name == "wpt") {break;}
                     }
                     _ => {}
                 }
@@ -306,9 +328,12 @@ pub mod gpx_parser {
             loop {
                 match self.parser.next() {
                     Ok(XmlEvent::Characters(str)) => {
+                        // Names and comments are taken as they are, numbers and times may be indented
+                        let text = str.clone();
+                        let str = String::from(str.trim());
                         match tag {
-                            "name" => { p.name = String::from(str); }
-                            "comment" => { p.comment = String::from(str); }
+                            "name" => { p.name = text; }
+                            "comment" => { p.comment = text; }
                             "ele" => { p.altitude =  str.parse::<f64>().unwrap(); }
                             "alt" => { p.altitude = str.parse::<f64>().unwrap(); }
                             "altitude" => { p.altitude = str.parse::<f64>().unwrap(); }
@@ -332,6 +357,9 @@ pub mod gpx_parser {
                             "weather" => { p.weather = Weather((str.parse::<u8>().unwrap()))}
                             "icon" => {
 
+                            }
+                            "text" => {
+
                             }
                             "time" => {
                                 let a =  NaiveDateTime::parse_from_str(str.as_str(), "%Y-%m-%dT%H:%M:0%SZ");
@@ -384,4 +412,41 @@ pub mod gpx_parser {
             }
         }
     }
-}
\ No newline at end of file
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::geometry::geometry_core::LatLon;
+
+        fn dms(d: f64, m: f64, s: f64) -> f64 {
+            d + m / 60f64 + s / 3600f64
+        }
+
+        // The Japanese origin of longitude and latitude in Tokyo Datum, read as WGS84 (JGD2000)
+        #[test]
+        fn tokyo_datum() {
+            let (lat, lon) = (dms(35f64, 39f64, 17.5148), dms(139f64, 44f64, 40.5020));
+            let gpx = format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                <gpx version=\"1.1\" creator=\"test\"><trk><name>Origin</name><trkseg>\
+                <trkpt lat=\"{lat}\" lon=\"{lon}\"><ele>25</ele></trkpt>\
+                <trkpt lat=\"{lat}\" lon=\"{lon}\"><ele>25</ele></trkpt>\
+                </trkseg></trk></gpx>\n");
+            let path = std::env::temp_dir().join("geotool_tokyo_datum.gpx");
+            std::fs::write(&path, gpx).unwrap();
+
+            let jgd = LatLon::new(dms(35f64, 39f64, 29.1572), dms(139f64, 44f64, 28.8759));
+            let mut parser = GPXParser::new(&path.clone().into_os_string()).unwrap();
+            parser.set_datum(Datum::Tokyo, DatumTransform::new());
+            let track = parser.open().unwrap();
+            let points = &track.routes[0].segments[0].points;
+            assert_eq!(points.len(), 2);
+            assert!(points.iter().all(|p| p.point.location.geodesic_distance(&jgd) < 5f64));
+            assert_eq!(points[0].point.altitude, 25f64);
+
+            // Without the datum the coordinates are taken as they are, about 450m off
+            let track = GPXParser::new(&path.into_os_string()).unwrap().open().unwrap();
+            let p = &track.routes[0].segments[0].points[0].point;
+            assert!(p.location.geodesic_distance(&jgd) > 400f64);
+        }
+    }
+}
@@ -2,7 +2,7 @@ pub mod gpx_writer {
     use std::fs::File;
     use std::io;
     use std::io::{Write};
-    use chrono::{DateTime, Utc};
+    use chrono::{DateTime, SecondsFormat, Utc};
     use crate::gpx::gpx::*;
     use xmlwriter::XmlWriter;
     use xmlwriter::Options;
@@ -35,20 +35,19 @@ pub mod gpx_writer {
             writer.write_attribute("xmlns", "http://www.topografix.com/GPX/1/1");
             writer.write_attribute("creator", "geotool");
 
+            for point in &track.waypoints {
+                self.write_point(&mut writer, "wpt", point);
+            }
             for route in &track.routes {
                 self.write_route(&mut writer, &route);
             }
             if !track.comment.is_empty() || !track.name.is_empty() {
                 writer.start_element("extensions");
                 if !track.name.is_empty()  {
-                    writer.start_element("name");
-                    writer.write_text(&track.name);
-                    writer.end_element();
+                    write_value(&mut writer, "name", &track.name);
                 }
                 if !track.comment.is_empty()  {
-                    writer.start_element("comment");
-                    writer.write_text(&track.comment);
-                    writer.end_element();
+                    write_value(&mut writer, "comment", &track.comment);
                 }
                 writer.end_element();
             }
@@ -60,9 +59,7 @@ pub mod gpx_writer {
         fn write_route(self: &mut Self, writer: &mut XmlWriter, route: &TrackRoute) {
             writer.start_element("trk");
             if !route.name.is_empty() {
-                writer.start_element("name");
-                writer.write_text(&route.name);
-                writer.end_element();
+                write_value(writer, "name", &route.name);
             }
             if !route.segments.is_empty() {
                 self.write_segments(writer, &route.segments);
@@ -74,16 +71,12 @@ pub mod gpx_writer {
             for seg in segments {
                 writer.start_element("trkseg");
                 if !seg.name.is_empty() {
-                    writer.start_element("name");
-                    writer.write_text(&seg.name);
-                    writer.end_element();
+                    write_value(writer, "name", &seg.name);
                 }
                 if !seg.comment.is_empty() {
                     writer.start_element("extensions");
                     if !seg.comment.is_empty() {
-                        writer.start_element("comment");
-                        writer.write_text(&seg.comment);
-                        writer.end_element();
+                        write_value(writer, "comment", &seg.comment);
                     }
                     writer.end_element();
                 }
@@ -93,105 +86,155 @@ pub mod gpx_writer {
         }
         fn write_points(self: &mut Self, writer: &mut XmlWriter, points: &Vec<PointAttr>) {
             for p in points {
-                writer.start_element("trkpt");
-                writer.write_attribute("lat", &p.point.location.lat);
-                writer.write_attribute("lon", &p.point.location.lon);
-                if !p.point.altitude.is_nan() {
-                    writer.start_element("ele");
-                    writer.write_text(&p.point.altitude.to_string());
-                    writer.end_element();
+                self.write_point(writer, "trkpt", &p.point);
+            }
+        }
+
+        fn write_point(self: &mut Self, writer: &mut XmlWriter, tag: &str, p: &TrackPoint) {
+            writer.start_element(tag);
+            writer.write_attribute("lat", &p.location.lat);
+            writer.write_attribute("lon", &p.location.lon);
+            if !p.altitude.is_nan() {
+                write_value(writer, "ele", &p.altitude.to_string());
+            }
+            if p.time != DateTime::<Utc>::MIN_UTC {
+                write_value(writer, "time", &p.time.to_rfc3339_opts(SecondsFormat::Millis, true));
+            }
+            if !p.link.is_empty() {
+                writer.start_element("link");
+                writer.write_attribute("href", &p.link);
+                writer.end_element();
+            }
+            if p.has_extension() {
+                writer.start_element("extensions");
+                if !p.comment.is_empty() {
+                    write_value(writer, "comment", &p.comment);
+                }
+                if !p.name.is_empty() {
+                    write_value(writer, "name", &p.name);
+                }
+                if !p.heading.is_nan() {
+                    write_value(writer, "heading", &p.heading.to_string());
+                }
+                if !p.pressure.is_nan() {
+                    write_value(writer, "pressure", &p.pressure.to_string());
+                }
+                if !p.temperature.is_nan() {
+                    write_value(writer, "temperature", &p.temperature.to_string());
+                }
+                if !p.heart_rate.is_nan() {
+                    write_value(writer, "heart_rate", &p.heart_rate.to_string());
+                }
+                if !p.luminance.is_nan() {
+                    write_value(writer, "luminance", &p.luminance.to_string());
+                }
+                if !p.radiation.is_nan() {
+                    write_value(writer, "radiation", &p.radiation.to_string());
+                }
+                if !p.energy.is_nan() {
+                    write_value(writer, "energy", &p.energy.to_string());
+                }
+                if !p.cadence.is_nan() {
+                    write_value(writer, "cadence", &p.cadence.to_string());
+                }
+                if !p.pace.is_nan() {
+                    write_value(writer, "pace", &p.pace.to_string());
+                }
+                if !p.vertical_speed.is_nan() {
+                    write_value(writer, "vertical_speed", &p.vertical_speed.to_string());
+                }
+                if p.weather != gpx::Weather(Weather::NONE) {
+                    write_value(writer, "vertical_speed", &p.vertical_speed.to_string());
                 }
-                if p.point.time != DateTime::<Utc>::MIN_UTC {
-                    writer.start_element("time");
-                    writer.write_text(&p.point.time.to_string());
+                if ((!p.wind.direction.is_nan()) && (p.wind.strong == u64::MIN)) {
+                    writer.start_element("wind");
+                    writer.write_attribute("direction", &p.wind.direction);
+                    writer.write_attribute("strong", &p.wind.strong);
                     writer.end_element();
                 }
-                if p.point.has_extension() {
-                    writer.start_element("extensions");
-                    if !p.point.comment.is_empty() {
-                        writer.start_element("comment");
-                        writer.write_text(&p.point.comment);
-                        writer.end_element();
-                    }
-                    if !p.point.name.is_empty() {
-                        writer.start_element("name");
-                        writer.write_text(&p.point.name);
-                        writer.end_element();
-                    }
-                    if !p.point.heading.is_nan() {
-                        writer.start_element("heading");
-                        writer.write_text(&p.point.heading.to_string());
-                        writer.end_element();
-                    }
-                    if !p.point.pressure.is_nan() {
-                        writer.start_element("pressure");
-                        writer.write_text(&p.point.pressure.to_string());
-                        writer.end_element();
-                    }
-                    if !p.point.temperature.is_nan() {
-                        writer.start_element("temperature");
-                        writer.write_text(&p.point.temperature.to_string());
-                        writer.end_element();
-                    }
-                    if !p.point.heart_rate.is_nan() {
-                        writer.start_element("heart_rate");
-                        writer.write_text(&p.point.heart_rate.to_string());
-                        writer.end_element();
-                    }
-                    if !p.point.luminance.is_nan() {
-                        writer.start_element("luminance");
-                        writer.write_text(&p.point.luminance.to_string());
-                        writer.end_element();
-                    }
-                    if !p.point.radiation.is_nan() {
-                        writer.start_element("radiation");
-                        writer.write_text(&p.point.radiation.to_string());
-                        writer.end_element();
-                    }
-                    if !p.point.energy.is_nan() {
-                        writer.start_element("energy");
-                        writer.write_text(&p.point.energy.to_string());
-                        writer.end_element();
-                    }
-                    if !p.point.cadence.is_nan() {
-                        writer.start_element("cadence");
-                        writer.write_text(&p.point.cadence.to_string());
-                        writer.end_element();
-                    }
-                    if !p.point.pace.is_nan() {
-                        writer.start_element("pace");
-                        writer.write_text(&p.point.pace.to_string());
-                        writer.end_element();
-                    }
-                    if !p.point.vertical_speed.is_nan() {
-                        writer.start_element("vertical_speed");
-                        writer.write_text(&p.point.vertical_speed.to_string());
-                        writer.end_element();
-                    }
-                    if p.point.weather != gpx::Weather(Weather::NONE) {
-                        writer.start_element("vertical_speed");
-                        writer.write_text(&p.point.vertical_speed.to_string());
-                        writer.end_element();
-                    }
-                    if ((!p.point.wind.direction.is_nan()) && (p.point.wind.strong == u64::MIN)) {
-                        writer.start_element("wind");
-                        writer.write_attribute("direction", &p.point.wind.direction);
-                        writer.write_attribute("strong", &p.point.wind.strong);
-                        writer.end_element();
-                    }
-                    if (!p.point.point_type.is_empty()) {
-                        writer.start_element("type");
-                        for t in &p.point.point_type {
-                            writer.start_element("value");
-                            writer.write_text(&(*t as i64).to_string());
-                            writer.end_element();
-                        }
-                        writer.end_element();
+                if (!p.point_type.is_empty()) {
+                    writer.start_element("type");
+                    for t in &p.point_type {
+                        write_value(writer, "value", &(*t as i64).to_string());
                     }
                     writer.end_element();
                 }
                 writer.end_element();
             }
+            writer.end_element();
+        }
+    }
+
+    // Text element without the indent around the text, so the value is read back as it is
+    fn write_value(writer: &mut XmlWriter, tag: &str, text: &str) {
+        writer.start_element(tag);
+        writer.set_preserve_whitespaces(true);
+        writer.write_text(text);
+        writer.end_element();
+        writer.set_preserve_whitespaces(false);
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use chrono::{Duration, TimeZone};
+        use crate::gpxperser::gpx_parser::GPXParser;
+
+        #[test]
+        fn round_trip() {
+            let start = Utc.with_ymd_and_hms(2023, 8, 1, 6, 0, 0).unwrap();
+            let mut segment = TrackSegment::new();
+            for i in 0..3 {
+                let mut p = TrackPoint::new(35.0 + i as f64 * 0.001, 139.0);
+                p.altitude = 1000.5 + i as f64;
+                p.time = start + Duration::milliseconds(i * 1500);
+                p.heart_rate = 120f64;
+                segment.add_point(p);
+            }
+            segment.points[1].point.name = String::from(" Junction  2 ");
+            segment.points[1].point.comment = String::from("Take the left <path>");
+            let mut route = TrackRoute::new();
+            route.add_segment(&segment);
+            let mut track = Track::new();
+            track.routes.push(route);
+            let mut hut = TrackPoint::new(35.01, 139.01);
+            hut.name = String::from("Kita hut");
+            hut.altitude = 2500f64;
+            hut.time = start + Duration::hours(2);
+            track.add_waypoint(hut);
+            // Photo waypoint as made by add_photo_waypoints
+            let mut photo = TrackPoint::new(35.0015, 139.0);
+            photo.name = String::from("IMG_0001.JPG");
+            photo.link = String::from("photos/IMG_0001.JPG");
+            photo.time = start + Duration::seconds(3);
+            track.add_waypoint(photo);
+
+            let path = std::env::temp_dir().join("geotool_round_trip.gpx");
+            GpxWriter::new(path.to_str().unwrap()).unwrap().write(&track);
+            let read = GPXParser::new(&path.into_os_string()).unwrap().open().unwrap();
+
+            // wpt stays out of the routes
+            assert_eq!(read.waypoints.len(), 2);
+            assert_eq!(read.waypoints[0].name, "Kita hut");
+            assert_eq!(read.waypoints[0].altitude, 2500f64);
+            assert_eq!(read.waypoints[0].time, start + Duration::hours(2));
+            assert!(read.waypoints[0].link.is_empty());
+            assert_eq!(read.waypoints[1].name, "IMG_0001.JPG");
+            assert_eq!(read.waypoints[1].link, "photos/IMG_0001.JPG");
+            assert_eq!(read.waypoints[1].time, start + Duration::seconds(3));
+            let points = &read.routes[0].segments[0].points;
+            assert_eq!(points.len(), 3);
+            for (a, b) in points.iter().zip(&segment.points) {
+                assert_eq!((a.point.location.lat, a.point.location.lon), (b.point.location.lat, b.point.location.lon));
+                assert_eq!(a.point.altitude, b.point.altitude);
+                // Milliseconds are kept
+                assert_eq!(a.point.time, b.point.time);
+                assert_eq!(a.point.heart_rate, 120f64);
+            }
+            // Names and comments come back as they are
+            assert_eq!(points[1].point.name, " Junction  2 ");
+            assert_eq!(points[1].point.comment, "Take the left <path>");
+            assert!(points[0].point.name.is_empty());
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,246 @@
+
+//
+// Grid reference tools
+//
+// -- Grid reference system --
+// [Geohash] base32 string, precision: 1 ~ 12 characters
+// [Maidenhead] field(AA) square(00) subsquare(aa) extended(00) ..., precision: 1 ~ 5 pairs
+// [MGRS] zone band 100km-square easting northing (e.g. 54SUE 12345 67890), precision: 0 ~ 5 digits
+//        Polar regions (UPS) are not supported.
+//
+// Decoding returns the Area of the cell.
+//
+pub mod grid_reference {
+    use crate::geometry::geometry_core::{Area, LatLon};
+    use crate::projection::projection::{ProjectedCoord, Zone};
+
+    const GEOHASH_BASE32: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+    const MGRS_BAND: &[u8] = b"CDEFGHJKLMNPQRSTUVWX";
+    const MGRS_COLUMN: [&[u8]; 3] = [b"STUVWXYZ", b"ABCDEFGH", b"JKLMNPQR"];
+    const MGRS_ROW: &[u8] = b"ABCDEFGHJKLMNPQRSTUV";
+
+    impl LatLon {
+        pub fn geohash(&self, precision: usize) -> String {
+            let mut lat = (-90f64, 90f64);
+            let mut lon = (-180f64, 180f64);
+            let mut hash = String::new();
+            let mut bits = 0u8;
+            let mut n = 0;
+            let mut even = true;
+            while hash.len() < precision.clamp(1, 12) {
+                let range = if even { &mut lon } else { &mut lat };
+                let value = if even { self.lon } else { self.lat };
+                let mid = (range.0 + range.1) / 2f64;
+                bits <<= 1;
+                if value >= mid {
+                    bits |= 1;
+                    range.0 = mid;
+                } else {
+                    range.1 = mid;
+                }
+                even = !even;
+                n += 1;
+                if n == 5 {
+                    hash.push(GEOHASH_BASE32[bits as usize] as char);
+                    bits = 0;
+                    n = 0;
+                }
+            }
+            hash
+        }
+
+        pub fn from_geohash(hash: &str) -> Option<Area> {
+            let mut lat = (-90f64, 90f64);
+            let mut lon = (-180f64, 180f64);
+            let mut even = true;
+            if hash.is_empty() {
+                return None;
+            }
+            for c in hash.to_ascii_lowercase().bytes() {
+                let bits = GEOHASH_BASE32.iter().position(|x| *x == c)?;
+                for i in (0..5).rev() {
+                    let range = if even { &mut lon } else { &mut lat };
+                    let mid = (range.0 + range.1) / 2f64;
+                    if (bits >> i) & 1 == 1 {
+                        range.0 = mid;
+                    } else {
+                        range.1 = mid;
+                    }
+                    even = !even;
+                }
+            }
+            Some(Area::new(lat.1, lon.0, lat.0, lon.1))
+        }
+
+        pub fn maidenhead(&self, precision: usize) -> String {
+            let mut lon = (self.lon + 180f64).clamp(0f64, 360f64 - 1e-9);
+            let mut lat = (self.lat + 90f64).clamp(0f64, 180f64 - 1e-9);
+            let mut size = (20f64, 10f64);
+            let mut locator = String::new();
+            for pair in 0..precision.clamp(1, 5) {
+                let (base, divisions) = maidenhead_pair(pair);
+                if pair != 0 {
+                    size = (size.0 / divisions, size.1 / divisions);
+                }
+                let x = (lon / size.0).floor();
+                let y = (lat / size.1).floor();
+                lon -= x * size.0;
+                lat -= y * size.1;
+                locator.push((base + x as u8) as char);
+                locator.push((base + y as u8) as char);
+            }
+            locator
+        }
+
+        pub fn from_maidenhead(locator: &str) -> Option<Area> {
+            let bytes = locator.as_bytes();
+            if bytes.is_empty() || !bytes.len().is_multiple_of(2) || bytes.len() > 10 {
+                return None;
+            }
+            let mut west = -180f64;
+            let mut south = -90f64;
+            let mut size = (20f64, 10f64);
+            for pair in 0..bytes.len() / 2 {
+                let (base, divisions) = maidenhead_pair(pair);
+                let x = bytes[pair * 2].to_ascii_uppercase().checked_sub(base)? as f64;
+                let y = bytes[pair * 2 + 1].to_ascii_uppercase().checked_sub(base)? as f64;
+                if x >= divisions || y >= divisions {
+                    return None;
+                }
+                if pair != 0 {
+                    size = (size.0 / divisions, size.1 / divisions);
+                }
+                west += x * size.0;
+                south += y * size.1;
+            }
+            Some(Area::new(south + size.1, west, south, west + size.0))
+        }
+
+        pub fn mgrs(&self, precision: usize) -> Option<String> {
+            if (self.lat < -80f64) || (self.lat >= 84f64) {
+                return None;
+            }
+            let band = MGRS_BAND[(((self.lat + 80f64) / 8f64).floor() as usize).min(MGRS_BAND.len() - 1)];
+            let p = ProjectedCoord::utm(self);
+            let zone = match p.zone {
+                Zone::Utm { zone, .. } => { zone }
+                _ => { return None; }
+            };
+            let column = MGRS_COLUMN[zone as usize % 3][((p.easting / 100000f64).floor() as usize).clamp(1, 8) - 1];
+            let row_index = ((p.northing / 100000f64).floor() as usize + if zone.is_multiple_of(2) { 5 } else { 0 }) % 20;
+            let row = MGRS_ROW[row_index];
+
+            let precision = precision.min(5);
+            let unit = 10f64.powi(5 - precision as i32);
+            let e = ((p.easting % 100000f64) / unit).floor() as u64;
+            let n = ((p.northing % 100000f64) / unit).floor() as u64;
+            let mut s = format!("{:02}{}{}{}", zone, band as char, column as char, row as char);
+            if precision > 0 {
+                s.push_str(&format!(" {:0w$} {:0w$}", e, n, w = precision));
+            }
+            Some(s)
+        }
+
+        pub fn from_mgrs(reference: &str) -> Option<Area> {
+            let s: String = reference.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_ascii_uppercase();
+            let digits = s.bytes().take_while(|c| c.is_ascii_digit()).count();
+            if digits == 0 || digits > 2 || s.len() < digits + 3 {
+                return None;
+            }
+            let zone: u8 = s[..digits].parse().ok()?;
+            if zone == 0 || zone > 60 {
+                return None;
+            }
+            let bytes = s.as_bytes();
+            let band = MGRS_BAND.iter().position(|x| *x == bytes[digits])?;
+            let column = MGRS_COLUMN[zone as usize % 3].iter().position(|x| *x == bytes[digits + 1])?;
+            let row = MGRS_ROW.iter().position(|x| *x == bytes[digits + 2])?;
+            let numbers = &s[digits + 3..];
+            if !numbers.len().is_multiple_of(2) || numbers.len() > 10 || !numbers.bytes().all(|c| c.is_ascii_digit()) {
+                return None;
+            }
+            let precision = numbers.len() / 2;
+            let unit = 10f64.powi(5 - precision as i32);
+            let e: f64 = if precision == 0 { 0f64 } else { numbers[..precision].parse::<f64>().ok()? * unit };
+            let n: f64 = if precision == 0 { 0f64 } else { numbers[precision..].parse::<f64>().ok()? * unit };
+
+            let north = band >= 10;
+            let utm = Zone::Utm { zone, north };
+            let easting = (column + 1) as f64 * 100000f64 + e;
+            let row = (row + 20 - if zone.is_multiple_of(2) { 5 } else { 0 }) % 20;
+            let mut northing = row as f64 * 100000f64 + n;
+
+            // The row letters repeat every 2000km, find the cycle which falls in the latitude band
+            let band_lat = band as f64 * 8f64 - 80f64;
+            let band_northing = ProjectedCoord::from_latlon(&LatLon::new(band_lat, utm.origin().lon), utm).northing;
+            let band_northing = (band_northing / 100000f64).floor() * 100000f64;
+            while northing < band_northing {
+                northing += 2000000f64;
+            }
+
+            let mut cell = Area::invalid();
+            for (de, dn) in [(0f64, 0f64), (unit, 0f64), (0f64, unit), (unit, unit)] {
+                let p = ProjectedCoord { zone: utm, easting: easting + de, northing: northing + dn };
+                cell.enter(&p.latlon());
+            }
+            Some(cell)
+        }
+    }
+
+    // Character base and number of divisions of the Maidenhead pair
+    fn maidenhead_pair(pair: usize) -> (u8, f64) {
+        match pair {
+            0 => { (b'A', 18f64) }
+            x if x % 2 == 1 => { (b'0', 10f64) }
+            _ => { (b'A', 24f64) }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn center(a: &Area) -> (f64, f64) {
+            ((a.north_west.lat + a.south_east.lat) / 2f64, (a.north_west.lon + a.south_east.lon) / 2f64)
+        }
+
+        #[test]
+        fn geohash() {
+            let l = LatLon::new(57.64911, 10.40744);
+            assert_eq!(l.geohash(11), "u4pruydqqvj");
+            let a = LatLon::from_geohash("u4pruydqqvj").unwrap();
+            let c = center(&a);
+            assert!((c.0 - l.lat).abs() < 1e-5 && (c.1 - l.lon).abs() < 1e-5);
+            assert!(LatLon::from_geohash("u4pa").is_none());
+        }
+
+        #[test]
+        fn maidenhead() {
+            // Munich
+            let l = LatLon::new(48.14666, 11.60833);
+            assert_eq!(l.maidenhead(3), "JN58TD");
+            let a = LatLon::from_maidenhead("JN58td").unwrap();
+            assert!(l.lat < a.north_west.lat && l.lat > a.south_east.lat);
+            assert!(l.lon > a.north_west.lon && l.lon < a.south_east.lon);
+            // Tokyo
+            assert_eq!(LatLon::new(35.681236, 139.767125).maidenhead(2), "PM95");
+        }
+
+        #[test]
+        fn mgrs() {
+            // Mt. Fuji, easting 293551.585, northing 3915398.347 in UTM 54N
+            let l = LatLon::new(35.360556, 138.727778);
+            assert_eq!(l.mgrs(5).unwrap(), "54STE 93551 15398");
+            assert_eq!(l.mgrs(0).unwrap(), "54STE");
+            let a = LatLon::from_mgrs("54STE 93551 15398").unwrap();
+            let c = center(&a);
+            assert!((c.0 - l.lat).abs() < 1e-4 && (c.1 - l.lon).abs() < 1e-4);
+
+            // Southern hemisphere round trip, Sydney
+            let l = LatLon::new(-33.856784, 151.215297);
+            let a = LatLon::from_mgrs(&l.mgrs(4).unwrap()).unwrap();
+            assert!(l.lat < a.north_west.lat && l.lat > a.south_east.lat);
+            assert!(l.lon > a.north_west.lon && l.lon < a.south_east.lon);
+        }
+    }
+}
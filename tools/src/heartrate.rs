@@ -0,0 +1,228 @@
+//
+// Heart rate tools
+//
+// -- Zone -- lower bounds as the fraction of
+// [MaxHr] maximum heart rate
+// [Reserve] heart rate reserve (Karvonen), rest + fraction * (max - rest)
+// Zone 0 is below the first bound, default bounds 50/60/70/80/90% make zone 1 ~ 5.
+//
+// -- Training load --
+// [TRIMP] Banister, sum of minutes * HRr * 0.64 * exp(b * HRr), HRr = (HR - rest) / (max - rest)
+//         b = 1.92 (male), 1.67 (female)
+// [hrTSS] TRIMP relative to one hour at the threshold heart rate, * 100
+//
+// A leg (from the previous point) takes the average heart rate of its known ends.
+// Legs without heart rate or time, or longer than Athlete::max_gap (a pause of the logging) are counted as missing.
+//
+pub mod heart_rate {
+    use chrono::Duration;
+    use crate::gpx::gpx::{seconds, Track, TrackRoute, TrackSegment};
+
+    pub const TRIMP_MALE: f64 = 1.92;
+    pub const TRIMP_FEMALE: f64 = 1.67;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum ZoneBasis {
+        MaxHr,
+        Reserve,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Athlete {
+        pub max_hr: f64,
+        pub rest_hr: f64,
+        pub threshold_hr: f64,      // Lactate threshold heart rate for hrTSS
+        pub trimp_factor: f64,      // b of TRIMP
+        pub basis: ZoneBasis,
+        pub zones: Vec<f64>,        // Lower bounds of zone 1, 2, ... as fraction
+        pub max_gap: Duration,      // Longest leg counted, longer legs are missing
+    }
+
+    impl Athlete {
+        // Threshold heart rate defaults to 85% of the reserve
+        // max_gap defaults to 2 minutes, long enough for the devices logging every minute
+        pub fn new(max_hr: f64, rest_hr: f64) -> Athlete {
+            Self {
+                max_hr,
+                rest_hr,
+                threshold_hr: rest_hr + 0.85 * (max_hr - rest_hr),
+                trimp_factor: TRIMP_MALE,
+                basis: ZoneBasis::MaxHr,
+                zones: vec![0.5, 0.6, 0.7, 0.8, 0.9],
+                max_gap: Duration::minutes(2),
+            }
+        }
+
+        // Lower bounds of the zones (bpm)
+        pub fn zone_bounds(&self) -> Vec<f64> {
+            self.zones.iter().map(|f| match self.basis {
+                ZoneBasis::MaxHr => { self.max_hr * f }
+                ZoneBasis::Reserve => { self.rest_hr + (self.max_hr - self.rest_hr) * f }
+            }).collect()
+        }
+
+        // 0 below the first zone, None for NaN
+        pub fn zone(&self, hr: f64) -> Option<usize> {
+            if hr.is_nan() {
+                return None;
+            }
+            Some(self.zone_bounds().iter().filter(|b| hr >= **b).count())
+        }
+
+        // TRIMP per minute at the heart rate
+        fn trimp_rate(&self, hr: f64) -> f64 {
+            let r = ((hr - self.rest_hr) / (self.max_hr - self.rest_hr)).clamp(0f64, 1f64);
+            r * 0.64 * (self.trimp_factor * r).exp()
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct HeartRateAnalysis {
+        pub zones: Vec<Duration>,   // Time in zone 0, 1, ...
+        pub measured: Duration,
+        pub missing: Duration,
+        pub average: f64,           // bpm, time weighted, NaN without heart rate
+        pub max: f64,
+        pub trimp: f64,
+        pub hr_tss: f64,
+    }
+
+    impl HeartRateAnalysis {
+        pub fn new(athlete: &Athlete) -> HeartRateAnalysis {
+            Self {
+                zones: vec![Duration::zero(); athlete.zones.len() + 1],
+                measured: Duration::zero(),
+                missing: Duration::zero(),
+                average: f64::NAN,
+                max: f64::NAN,
+                trimp: 0f64,
+                hr_tss: 0f64,
+            }
+        }
+
+        pub fn add(self: &mut Self, a: &HeartRateAnalysis) {
+            for (z, t) in self.zones.iter_mut().zip(&a.zones) {
+                *z = *z + *t;
+            }
+            let (m1, m2) = (seconds(&self.measured), seconds(&a.measured));
+            if m2 > 0f64 {
+                self.average = if m1 > 0f64 { (self.average * m1 + a.average * m2) / (m1 + m2) } else { a.average };
+            }
+            self.measured = self.measured + a.measured;
+            self.missing = self.missing + a.missing;
+            self.max = self.max.max(a.max);
+            self.trimp += a.trimp;
+            self.hr_tss += a.hr_tss;
+        }
+    }
+
+    impl TrackSegment {
+        pub fn heart_rate_analysis(&self, athlete: &Athlete) -> HeartRateAnalysis {
+            let mut a = HeartRateAnalysis::new(athlete);
+            let mut sum = 0f64;
+            for w in self.points.windows(2) {
+                let (p, q) = (&w[0].point, &w[1].point);
+                if !p.has_time() || !q.has_time() || q.time <= p.time {
+                    continue;
+                }
+                let dt = q.time - p.time;
+                let hr = match (p.heart_rate.is_nan(), q.heart_rate.is_nan()) {
+                    (false, false) => { (p.heart_rate + q.heart_rate) / 2f64 }
+                    (false, true) => { p.heart_rate }
+                    (true, false) => { q.heart_rate }
+                    (true, true) => { f64::NAN }
+                };
+                if hr.is_nan() || dt > athlete.max_gap {
+                    a.missing = a.missing + dt;
+                    continue;
+                }
+                if let Some(z) = athlete.zone(hr) {
+                    a.zones[z] = a.zones[z] + dt;
+                }
+                a.measured = a.measured + dt;
+                sum += hr * seconds(&dt);
+                a.trimp += athlete.trimp_rate(hr) * seconds(&dt) / 60f64;
+            }
+            a.max = self.points.iter().map(|p| p.point.heart_rate).fold(f64::NAN, f64::max);
+            if a.measured > Duration::zero() {
+                a.average = sum / seconds(&a.measured);
+            }
+            a.hr_tss = a.trimp / (athlete.trimp_rate(athlete.threshold_hr) * 60f64) * 100f64;
+            a
+        }
+    }
+
+    impl TrackRoute {
+        pub fn heart_rate_analysis(&self, athlete: &Athlete) -> HeartRateAnalysis {
+            let mut a = HeartRateAnalysis::new(athlete);
+            for s in &self.segments {
+                a.add(&s.heart_rate_analysis(athlete));
+            }
+            a
+        }
+    }
+
+    impl Track {
+        pub fn heart_rate_analysis(&self, athlete: &Athlete) -> HeartRateAnalysis {
+            let mut a = HeartRateAnalysis::new(athlete);
+            for r in &self.routes {
+                a.add(&r.heart_rate_analysis(athlete));
+            }
+            a
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use chrono::{TimeZone, Utc};
+        use crate::gpx::gpx::TrackPoint;
+
+        #[test]
+        fn zones_and_load() {
+            // An hour at 148 bpm every 10 s, then 30 minutes without heart rate
+            let start = Utc.with_ymd_and_hms(2023, 8, 1, 6, 0, 0).unwrap();
+            let mut segment = TrackSegment::new();
+            for i in 0..=540 {
+                let mut p = TrackPoint::new(35.0, 139.0 + i as f64 * 0.0001);
+                p.time = start + Duration::seconds(i * 10);
+                p.heart_rate = if i <= 360 { 148f64 } else { f64::NAN };
+                segment.add_point(p);
+            }
+            let mut athlete = Athlete::new(190f64, 50f64);
+            let a = segment.heart_rate_analysis(&athlete);
+            // The leg after the last heart rate takes its value
+            assert_eq!(a.zones[3], Duration::seconds(3610));
+            assert_eq!(a.measured, Duration::seconds(3610));
+            assert_eq!(a.missing, Duration::seconds(1790));
+            assert_eq!(a.average, 148f64);
+            // HRr 0.7: 60 * 0.7 * 0.64 * exp(1.92 * 0.7)
+            assert!((a.trimp - 103.07 * 3610f64 / 3600f64).abs() < 0.1);
+            assert!((a.hr_tss - 61.75 * 3610f64 / 3600f64).abs() < 0.1);
+
+            assert_eq!(athlete.zone(160f64), Some(4));
+            athlete.basis = ZoneBasis::Reserve;
+            assert_eq!(athlete.zone(160f64), Some(3));
+            assert_eq!(athlete.zone(100f64), Some(0));
+            assert_eq!(athlete.zone(f64::NAN), None);
+
+            // A device logging every 60 s, counted unless the gap limit is shorter
+            let mut segment = TrackSegment::new();
+            for i in 0..=60 {
+                let mut p = TrackPoint::new(35.0, 139.0 + i as f64 * 0.0005);
+                p.time = start + Duration::seconds(i * 60);
+                p.heart_rate = 148f64;
+                segment.add_point(p);
+            }
+            let mut athlete = Athlete::new(190f64, 50f64);
+            let a = segment.heart_rate_analysis(&athlete);
+            assert_eq!(a.measured, Duration::hours(1));
+            assert_eq!(a.zones[3], Duration::hours(1));
+            assert!((a.trimp - 103.07).abs() < 0.1);
+            athlete.max_gap = Duration::seconds(30);
+            let a = segment.heart_rate_analysis(&athlete);
+            assert_eq!((a.measured, a.missing), (Duration::zero(), Duration::hours(1)));
+            assert_eq!(a.trimp, 0f64);
+        }
+    }
+}
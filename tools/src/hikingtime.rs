@@ -0,0 +1,180 @@
+//
+// Hiking time estimation tools
+//
+// -- Model -- (time while walking, breaks are not included)
+// [CourseTime] Yamakei style standard course time (コースタイム)
+//              horizontal 4 km/h + ascent 300 m/h + descent 500 m/h, added up
+// [Naismith] 5 km/h + 1 hour per 600m ascent, with Langmuir corrections for descent
+//            5 ~ 12 degree: -10 minutes per 300m, steeper than 12 degree: +10 minutes per 300m
+// [Tobler] hiking function, speed(km/h) = 6 * exp(-3.5 * |grade + 0.05|)
+//
+// The altitude profile is smoothed over PROFILE_WINDOW before grades are taken.
+// Pace is the personal factor, 1.0 for the standard, 1.2 for 20% slower.
+//
+pub mod hiking_time {
+    use chrono::Duration;
+    use crate::gpx::gpx::{Track, TrackSegment};
+    use crate::statistics::statistics::STOP_SPEED;
+
+    const PROFILE_WINDOW: f64 = 50f64;     // m
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Model {
+        CourseTime,
+        Naismith,
+        Tobler,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct HikingTime {
+        pub model: Model,
+        pub pace: f64,
+    }
+
+    impl HikingTime {
+        pub fn new(model: Model) -> HikingTime {
+            Self {model, pace: 1f64}
+        }
+
+        pub fn set_pace(self: &mut Self, pace: f64) {
+            self.pace = pace;
+        }
+
+        pub fn estimate(&self, segment: &TrackSegment) -> Duration {
+            Duration::milliseconds((self.standard_hours(segment) * self.pace * 3600000f64).round() as i64)
+        }
+
+        pub fn estimate_track(&self, track: &Track) -> Duration {
+            let mut total = Duration::zero();
+            for r in &track.routes {
+                for s in &r.segments {
+                    total = total + self.estimate(s);
+                }
+            }
+            total
+        }
+
+        // Fit the pace to the moving time of the past tracks, the segments without time are ignored
+        // Returns the new pace, None when no segment is usable
+        pub fn calibrate(self: &mut Self, tracks: &[Track]) -> Option<f64> {
+            let mut actual = 0f64;
+            let mut standard = 0f64;
+            for s in tracks.iter().flat_map(|t| t.routes.iter()).flat_map(|r| r.segments.iter()) {
+                let moving = s.statistics(STOP_SPEED).moving;
+                let hours = self.standard_hours(s);
+                if moving > Duration::zero() && hours > 0f64 {
+                    actual += moving.num_milliseconds() as f64 / 3600000f64;
+                    standard += hours;
+                }
+            }
+            if standard == 0f64 {
+                return None;
+            }
+            self.pace = actual / standard;
+            Some(self.pace)
+        }
+
+        // Hours with pace 1.0
+        fn standard_hours(&self, segment: &TrackSegment) -> f64 {
+            let altitudes = segment.smoothed_altitudes(PROFILE_WINDOW);
+            let mut hours = 0f64;
+            let (mut horizontal, mut ascent, mut descent) = (0f64, 0f64, 0f64);
+            for i in 1..segment.points.len() {
+                let h = segment.points[i - 1].point.location.great_circle_distance(&segment.points[i].point.location);
+                let dz = altitudes[i] - altitudes[i - 1];
+                let dz = if dz.is_nan() { 0f64 } else { dz };
+                horizontal += h;
+                if dz > 0f64 { ascent += dz } else { descent -= dz }
+                match self.model {
+                    Model::Naismith => {
+                        if dz < 0f64 && h > 0f64 {
+                            let angle = (-dz / h).atan().to_degrees();
+                            if angle > 12f64 {
+                                hours += -dz / 300f64 / 6f64;
+                            } else if angle >= 5f64 {
+                                hours -= -dz / 300f64 / 6f64;
+                            }
+                        }
+                    }
+                    Model::Tobler => {
+                        if h > 0f64 {
+                            hours += h / 1000f64 / (6f64 * (-3.5 * (dz / h + 0.05).abs()).exp());
+                        }
+                    }
+                    Model::CourseTime => {}
+                }
+            }
+            match self.model {
+                Model::CourseTime => { horizontal / 4000f64 + ascent / 300f64 + descent / 500f64 }
+                Model::Naismith => { (hours + horizontal / 5000f64 + ascent / 600f64).max(0f64) }
+                Model::Tobler => { hours }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use chrono::{TimeZone, Utc};
+        use crate::gpx::gpx::{TrackPoint, TrackRoute};
+
+        // Straight line toward north, climbing steadily, a point every 100m
+        fn climb(km: f64, ascent: f64) -> TrackSegment {
+            let mut segment = TrackSegment::new();
+            let n = (km * 10f64) as usize;
+            for i in 0..=n {
+                let mut p = TrackPoint::new(35.0 + i as f64 * 100f64 / 111195f64, 139.0);
+                p.altitude = 1000f64 + ascent * i as f64 / n as f64;
+                segment.add_point(p);
+            }
+            segment
+        }
+
+        fn hours(d: Duration) -> f64 {
+            d.num_seconds() as f64 / 3600f64
+        }
+
+        #[test]
+        fn models() {
+            let flat = climb(10f64, 0f64);
+            assert!((hours(HikingTime::new(Model::CourseTime).estimate(&flat)) - 2.5).abs() < 0.01);
+            assert!((hours(HikingTime::new(Model::Naismith).estimate(&flat)) - 2.0).abs() < 0.01);
+            assert!((hours(HikingTime::new(Model::Tobler).estimate(&flat)) - 1.986).abs() < 0.01);
+
+            let up = climb(5f64, 1000f64);
+            assert!((hours(HikingTime::new(Model::CourseTime).estimate(&up)) - 4.583).abs() < 0.01);
+            assert!((hours(HikingTime::new(Model::Naismith).estimate(&up)) - 2.667).abs() < 0.01);
+            // Langmuir: gentle descent (11.3 degree) is faster, steep descent (18.4 degree) is slower
+            let gentle = climb(5f64, -1000f64);
+            assert!((hours(HikingTime::new(Model::Naismith).estimate(&gentle)) - 0.444).abs() < 0.01);
+            let steep = climb(3f64, -1000f64);
+            assert!((hours(HikingTime::new(Model::Naismith).estimate(&steep)) - 1.156).abs() < 0.01);
+
+            let mut slow = HikingTime::new(Model::Tobler);
+            slow.set_pace(1.5);
+            let ratio = hours(slow.estimate(&flat)) / hours(HikingTime::new(Model::Tobler).estimate(&flat));
+            assert!((ratio - 1.5).abs() < 1e-3);
+        }
+
+        #[test]
+        fn calibrate() {
+            // Walked 1.2 times the course time without a break
+            let mut segment = climb(5f64, 500f64);
+            let mut model = HikingTime::new(Model::CourseTime);
+            let total = model.estimate(&segment).num_seconds() as f64 * 1.2;
+            let start = Utc.with_ymd_and_hms(2023, 8, 1, 6, 0, 0).unwrap();
+            let n = segment.points.len() - 1;
+            for (i, p) in segment.points.iter_mut().enumerate() {
+                p.point.time = start + Duration::seconds((total * i as f64 / n as f64) as i64);
+            }
+            let mut route = TrackRoute::new();
+            route.add_segment(&segment);
+            let mut track = Track::new();
+            track.routes.push(route);
+
+            let pace = model.calibrate(&[track]).unwrap();
+            assert!((pace - 1.2).abs() < 0.01);
+            assert!(model.calibrate(&[Track::new()]).is_none());
+        }
+    }
+}
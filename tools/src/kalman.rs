@@ -0,0 +1,265 @@
+//
+// Kalman filter and Rauch-Tung-Striebel smoother tools
+//
+// Each axis (east, north on the local tangent plane of the first point, and the altitude) is a constant velocity
+// model of [position, velocity], driven by white acceleration noise.
+// The forward pass filters the measured positions, the backward RTS pass smooths with the later points.
+//
+// -- Measurement --
+// [Position] the location and the altitude, NaN altitude is skipped
+// [Speed] TrackPoint.pace (m/s) along the direction of travel on the track smoothed without it, when use_speed
+// [VerticalSpeed] TrackPoint.vertical_speed (m/s), when use_vertical_speed
+// Points without time take no time step, they are averaged with the previous point.
+//
+pub mod kalman {
+    use chrono::{DateTime, Utc};
+    use crate::geodesic::geodesic::Ellipsoid;
+    use crate::gpx::gpx::{is_known_time, seconds, TrackSegment};
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct KalmanParams {
+        pub position_noise: f64,        // m, standard deviation of the horizontal position
+        pub altitude_noise: f64,        // m
+        pub acceleration: f64,          // m/s2, standard deviation of the horizontal acceleration
+        pub vertical_acceleration: f64, // m/s2
+        pub speed_noise: f64,           // m/s
+        pub vertical_speed_noise: f64,  // m/s
+        pub use_speed: bool,
+        pub use_vertical_speed: bool,
+    }
+
+    impl KalmanParams {
+        // Suits walking with a phone or a handheld GPS
+        pub fn new() -> KalmanParams {
+            Self {
+                position_noise: 5f64,
+                altitude_noise: 5f64,
+                acceleration: 0.5,
+                vertical_acceleration: 0.1,
+                speed_noise: 0.5,
+                vertical_speed_noise: 0.3,
+                use_speed: false,
+                use_vertical_speed: false,
+            }
+        }
+    }
+
+    // [position, velocity] and its covariance [[p00, p01], [p01, p11]]
+    #[derive(Debug, Clone, Copy)]
+    struct State {
+        x: [f64; 2],
+        p: [f64; 3],
+    }
+
+    impl State {
+        fn predict(&self, dt: f64, q: f64) -> State {
+            let [p00, p01, p11] = self.p;
+            State {
+                x: [self.x[0] + self.x[1] * dt, self.x[1]],
+                p: [p00 + 2f64 * dt * p01 + dt * dt * p11 + q * dt.powi(3) / 3f64,
+                    p01 + dt * p11 + q * dt * dt / 2f64,
+                    p11 + q * dt],
+            }
+        }
+
+        // Scalar measurement z of the state element k with the variance r
+        fn update(self: &mut Self, k: usize, z: f64, r: f64) {
+            if z.is_nan() {
+                return;
+            }
+            let [p00, p01, p11] = self.p;
+            let (pk, s) = if k == 0 { ([p00, p01], p00 + r) } else { ([p01, p11], p11 + r) };
+            let gain = [pk[0] / s, pk[1] / s];
+            let innovation = z - self.x[k];
+            self.x = [self.x[0] + gain[0] * innovation, self.x[1] + gain[1] * innovation];
+            self.p = [p00 - gain[0] * pk[0], p01 - gain[0] * pk[1], p11 - gain[1] * pk[1]];
+        }
+    }
+
+    // Forward filter and backward smoother of one axis, z: positions, v: velocities (NaN when not measured)
+    fn smooth_axis(z: &[f64], v: &[f64], dt: &[f64], r: f64, rv: f64, q: f64) -> Vec<f64> {
+        let n = z.len();
+        let first = match z.iter().position(|x| !x.is_nan()) {
+            Some(i) => { i }
+            None => { return vec![f64::NAN; n] }
+        };
+        let mut filtered: Vec<State> = Vec::with_capacity(n);
+        let mut predicted: Vec<State> = Vec::with_capacity(n);
+        let mut s = State {x: [z[first], 0f64], p: [r, 0f64, 100f64]};
+        for i in 0..n {
+            let p = if i == 0 { s } else { s.predict(dt[i], q) };
+            s = p;
+            s.update(0, z[i], r);
+            s.update(1, v[i], rv);
+            predicted.push(p);
+            filtered.push(s);
+        }
+
+        // Rauch-Tung-Striebel, C = P F' (P_pred)^-1
+        let mut smoothed = filtered.clone();
+        for i in (0..n - 1).rev() {
+            let (f, p, next) = (&filtered[i], &predicted[i + 1], &smoothed[i + 1]);
+            let d = dt[i + 1];
+            // P F' with F = [[1, dt], [0, 1]]
+            let pf = [[f.p[0] + d * f.p[1], f.p[1]], [f.p[1] + d * f.p[2], f.p[2]]];
+            let det = p.p[0] * p.p[2] - p.p[1] * p.p[1];
+            if det.abs() < 1e-12 {
+                continue;
+            }
+            let inv = [[p.p[2] / det, -p.p[1] / det], [-p.p[1] / det, p.p[0] / det]];
+            let c = [[pf[0][0] * inv[0][0] + pf[0][1] * inv[1][0], pf[0][0] * inv[0][1] + pf[0][1] * inv[1][1]],
+                     [pf[1][0] * inv[0][0] + pf[1][1] * inv[1][0], pf[1][0] * inv[0][1] + pf[1][1] * inv[1][1]]];
+            let dx = [next.x[0] - p.x[0], next.x[1] - p.x[1]];
+            let x = [f.x[0] + c[0][0] * dx[0] + c[0][1] * dx[1], f.x[1] + c[1][0] * dx[0] + c[1][1] * dx[1]];
+            // P_s = P + C (P_s(next) - P_pred) C'
+            let dp = [[next.p[0] - p.p[0], next.p[1] - p.p[1]], [next.p[1] - p.p[1], next.p[2] - p.p[2]]];
+            let cdp = [[c[0][0] * dp[0][0] + c[0][1] * dp[1][0], c[0][0] * dp[0][1] + c[0][1] * dp[1][1]],
+                       [c[1][0] * dp[0][0] + c[1][1] * dp[1][0], c[1][0] * dp[0][1] + c[1][1] * dp[1][1]]];
+            smoothed[i] = State {
+                x,
+                p: [f.p[0] + cdp[0][0] * c[0][0] + cdp[0][1] * c[0][1],
+                    f.p[1] + cdp[0][0] * c[1][0] + cdp[0][1] * c[1][1],
+                    f.p[2] + cdp[1][0] * c[1][0] + cdp[1][1] * c[1][1]],
+            };
+        }
+        smoothed.iter().map(|s| s.x[0]).collect()
+    }
+
+    impl TrackSegment {
+        // Smoothed copy of the segment, the other values of the points are kept
+        pub fn kalman_smooth(&self, params: &KalmanParams) -> TrackSegment {
+            let mut segment = TrackSegment::new();
+            segment.name = self.name.clone();
+            segment.comment = self.comment.clone();
+            segment.set_distance_model(self.distance_model());
+            segment.set_vertical_datum(self.vertical_datum());
+            if self.points.is_empty() {
+                return segment;
+            }
+
+            let radius = Ellipsoid::WGS84.mean_radius();
+            let origin = self.points[0].point.location;
+            let scale = origin.lat.to_radians().cos();
+            let n = self.points.len();
+            let mut dt = vec![0f64; n];
+            let mut last = DateTime::<Utc>::MIN_UTC;
+            for (i, p) in self.points.iter().enumerate() {
+                let t = p.point.time;
+                if p.point.has_time() {
+                    if is_known_time(&last) && t > last {
+                        dt[i] = seconds(&(t - last));
+                    }
+                    last = t;
+                }
+            }
+            let east: Vec<f64> = self.points.iter().map(|p| {
+                let dlon = (p.point.location.lon - origin.lon + 540f64).rem_euclid(360f64) - 180f64;
+                dlon.to_radians() * scale * radius
+            }).collect();
+            let north: Vec<f64> = self.points.iter().map(|p| (p.point.location.lat - origin.lat).to_radians() * radius).collect();
+            let up: Vec<f64> = self.points.iter().map(|p| p.point.altitude).collect();
+
+            let vu: Vec<f64> = self.points.iter()
+                .map(|p| if params.use_vertical_speed { p.point.vertical_speed } else { f64::NAN })
+                .collect();
+
+            let (r, rv) = (params.position_noise.powi(2), params.speed_noise.powi(2));
+            let q = params.acceleration.powi(2);
+            let none = vec![f64::NAN; n];
+            let mut smooth_east = smooth_axis(&east, &none, &dt, r, rv, q);
+            let mut smooth_north = smooth_axis(&north, &none, &dt, r, rv, q);
+            if params.use_speed {
+                // The direction of the raw legs swings with the noise, take it from the track smoothed without the speed
+                let (mut ve, mut vn) = (none.clone(), none.clone());
+                for i in 0..n {
+                    let (a, b) = (i.saturating_sub(1), (i + 1).min(n - 1));
+                    let (de, dn) = (smooth_east[b] - smooth_east[a], smooth_north[b] - smooth_north[a]);
+                    let length = de.hypot(dn);
+                    let speed = self.points[i].point.pace;
+                    if length > 0f64 && !speed.is_nan() {
+                        ve[i] = speed * de / length;
+                        vn[i] = speed * dn / length;
+                    }
+                }
+                smooth_east = smooth_axis(&east, &ve, &dt, r, rv, q);
+                smooth_north = smooth_axis(&north, &vn, &dt, r, rv, q);
+            }
+            let up = smooth_axis(&up, &vu, &dt, params.altitude_noise.powi(2),
+                                 params.vertical_speed_noise.powi(2), params.vertical_acceleration.powi(2));
+
+            for i in 0..n {
+                let mut p = self.points[i].point.clone();
+                p.location.lat = origin.lat + (smooth_north[i] / radius).to_degrees();
+                p.location.lon = (origin.lon + (smooth_east[i] / (radius * scale)).to_degrees() + 540f64).rem_euclid(360f64) - 180f64;
+                if !p.altitude.is_nan() {
+                    p.altitude = up[i];
+                }
+                segment.add_point(p);
+            }
+            segment
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use chrono::{Duration, TimeZone};
+        use crate::elevation::elevation::GainMethod;
+        use crate::gpx::gpx::TrackPoint;
+
+        #[test]
+        fn smooth() {
+            // 1.2 m/s toward east every 5 s, +-6m across the track, +-4m altitude on a 0.1 m/s climb
+            let start = Utc.with_ymd_and_hms(2023, 8, 1, 6, 0, 0).unwrap();
+            let mut segment = TrackSegment::new();
+            for i in 0..200 {
+                let across = [6f64, -4f64, -6f64, 4f64][i % 4];
+                let noise = [4f64, -2f64, -4f64, 2f64][(i / 2) % 4];
+                let mut p = TrackPoint::new(35.0 + across / 111195f64, 139.0 + i as f64 * 6f64 / 91290f64);
+                p.altitude = 1000f64 + i as f64 * 0.5 + noise;
+                p.time = start + Duration::seconds(i as i64 * 5);
+                p.pace = 1.2;
+                segment.add_point(p);
+            }
+            let mut params = KalmanParams::new();
+            let smoothed = segment.kalman_smooth(&params);
+            assert_eq!(smoothed.points.len(), segment.points.len());
+            assert_eq!(smoothed.points[10].point.time, segment.points[10].point.time);
+            // The zig-zag across the track is mostly gone
+            let error = |s: &TrackSegment| s.points[20..180].iter()
+                .map(|p| ((p.point.location.lat - 35.0) * 111195f64).abs()).fold(0f64, f64::max);
+            assert!(error(&smoothed) < 2f64);
+            assert!(smoothed.distance() < segment.distance() * 0.8);
+            // Within 5% of the straight line
+            assert!((smoothed.distance() / (199f64 * 6f64) - 1f64).abs() < 0.05);
+            let gain = smoothed.elevation_change(GainMethod::Raw).gain;
+            assert!(gain < segment.elevation_change(GainMethod::Raw).gain * 0.5);
+            assert!((gain - 99.5).abs() < 10f64);
+
+            params.use_speed = true;
+            let with_speed = segment.kalman_smooth(&params);
+            assert!(error(&with_speed) < 2f64);
+
+            // +-8m along the track with the right speed, the spacing of the points (6m) and the distance from
+            // the true positions get closer
+            let mut segment = TrackSegment::new();
+            for i in 0..200 {
+                let along = [8f64, -3f64, -8f64, 5f64, 0f64, -6f64, 3f64][i % 7];
+                let mut p = TrackPoint::new(35.0, 139.0 + (i as f64 * 6f64 + along) / 91290f64);
+                p.time = start + Duration::seconds(i as i64 * 5);
+                p.pace = 1.2;
+                segment.add_point(p);
+            }
+            let spacing = |s: &TrackSegment| (s.points[21..180].iter().map(|p| (p.distance() - 6f64).powi(2)).sum::<f64>() / 159f64).sqrt();
+            let truth = |s: &TrackSegment| (s.points[20..180].iter().enumerate()
+                .map(|(k, p)| ((p.point.location.lon - 139.0) * 91290f64 - (k + 20) as f64 * 6f64).powi(2)).sum::<f64>() / 160f64).sqrt();
+            params.use_speed = false;
+            let without = segment.kalman_smooth(&params);
+            params.use_speed = true;
+            let with = segment.kalman_smooth(&params);
+            // About half of the error without the speed
+            assert!(spacing(&with) < spacing(&without) * 0.7);
+            assert!(truth(&with) < truth(&without) * 0.7);
+        }
+    }
+}
@@ -1,9 +1,29 @@
 mod geometry;
+mod geodesic;
+mod projection;
+mod gridref;
+mod datum;
+mod geoid;
 mod cachedb;
 mod map;
 mod gpxperser;
 mod gpx;
 mod gpxwriter;
+mod polygonparser;
+mod spatialindex;
+mod simplify;
+mod statistics;
+mod elevation;
+mod hikingtime;
+mod splits;
+mod heartrate;
+mod climb;
+mod reststop;
+mod outlier;
+mod kalman;
+mod resample;
+mod position;
+mod geotag;
 
 use geometry::geometry_core::LatLon;
 use geometry::geometry_core::TileCoord;
@@ -51,13 +71,4 @@ fn main() {
 //    println!("{:?}", track);
 }
 
-fn max_tiles(z: u32) -> i32 {
-   2i32.pow(z)
-}
-
-fn coord_to_pixel(x: f64, y: f64, z: u32) -> (i64, i64) {
-   let total = (max_tiles(z) * 256) as f64;
-   ((x * total).trunc() as i64, (y * total).trunc() as i64)
-}
-
 
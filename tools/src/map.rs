@@ -3,6 +3,47 @@ pub mod map {
     use crate::geometry::geometry_core::LatLon;
     use crate::geometry::geometry_core::TileCoord;
     use crate::geometry::geometry_core::Geometry;
+    use crate::geometry::geometry_core::TileScheme;
+
+    // Tile server setting
+    // url: template with {z}, {x}, {y} and {q} (quadkey)
+    pub struct TileSource {
+        pub name: String,
+        pub url: String,
+        pub scheme: TileScheme,
+        pub tile_size: u32,
+        pub zoom_min: u32,
+        pub zoom_max: u32,
+    }
+
+    impl TileSource {
+        pub fn new(name: &str, url: &str, scheme: TileScheme) -> TileSource {
+            Self {
+                name: String::from(name),
+                url: String::from(url),
+                scheme,
+                tile_size: 256,
+                zoom_min: 0,
+                zoom_max: 18,
+            }
+        }
+
+        // URL of the tile given in XYZ
+        pub fn tile_url(&self, t: &TileCoord) -> Option<String> {
+            if (t.z < self.zoom_min) || (t.z > self.zoom_max) {
+                return None;
+            }
+            let y = match self.scheme {
+                TileScheme::Tms => { t.tms_y() }
+                _ => { t.ty }
+            };
+            Some(self.url
+                .replace("{z}", &t.z.to_string())
+                .replace("{x}", &t.tx.to_string())
+                .replace("{y}", &y.to_string())
+                .replace("{q}", &t.quadkey()))
+        }
+    }
 
     struct Map {
         center: LatLon,
@@ -0,0 +1,267 @@
+//
+// GPS outlier cleaning tools
+//
+// Every point is checked against the last accepted point, so a spike is caught without its neighbours.
+// The first point has nothing before it, it is accepted when the speed to most of the next ANCHOR_CHECK points
+// is within max_speed, else it is a Speed outlier and the next point is tried (a cold start jump).
+// -- Reason --
+// [DuplicateTime] the same or an earlier time than the last accepted point
+// [Speed] faster than max_speed (m/s) from the last accepted point
+// [Acceleration] speed changed more than max_acceleration (m/s2) from the last accepted leg
+// [VerticalRate] altitude changed faster than max_vertical_rate (m/s) from the last accepted point and the previous point,
+//                so only the points on a step of the altitude are taken, not the level after it
+// position_noise and altitude_noise (m) are taken off the leg before the rates, or 1 s logs trip on the jitter.
+// [ZigZag] turns back more than zigzag_angle (degree) with both legs longer than zigzag_distance (m),
+//          and the next point is closer to the last accepted point than to this point
+// Points without time are checked only for the zig-zag.
+//
+// -- Action --
+// [Flag] report only, the segment is not changed
+// [Remove] remove the outliers, the distances and directions of the points are recalculated
+//
+pub mod outlier {
+    use crate::gpx::gpx::{seconds, TrackPoint, TrackSegment};
+
+    const ANCHOR_CHECK: usize = 4;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Activity {
+        Hiking,
+        Running,
+        Cycling,
+        Driving,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct Limits {
+        pub max_speed: f64,             // m/s
+        pub max_acceleration: f64,      // m/s2
+        pub max_vertical_rate: f64,     // m/s
+        pub zigzag_angle: f64,          // degree
+        pub zigzag_distance: f64,       // m
+        pub position_noise: f64,        // m
+        pub altitude_noise: f64,        // m
+    }
+
+    impl Limits {
+        pub fn for_activity(activity: Activity) -> Limits {
+            let (max_speed, max_acceleration, max_vertical_rate) = match activity {
+                Activity::Hiking => { (4f64, 3f64, 1f64) }
+                Activity::Running => { (10f64, 5f64, 2f64) }
+                Activity::Cycling => { (25f64, 5f64, 3f64) }
+                Activity::Driving => { (70f64, 10f64, 10f64) }
+            };
+            Self {
+                max_speed,
+                max_acceleration,
+                max_vertical_rate,
+                zigzag_angle: 170f64,
+                zigzag_distance: 30f64,
+                position_noise: 10f64,
+                altitude_noise: 5f64,
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Reason {
+        DuplicateTime,
+        Speed,
+        Acceleration,
+        VerticalRate,
+        ZigZag,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Action {
+        Flag,
+        Remove,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct Outlier {
+        pub index: usize,       // Point index before the cleaning
+        pub reason: Reason,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct CleanReport {
+        pub outliers: Vec<Outlier>,
+        pub removed: usize,
+        pub distance_before: f64,   // m
+        pub distance_after: f64,    // m
+    }
+
+    impl CleanReport {
+        pub fn count(&self, reason: Reason) -> usize {
+            self.outliers.iter().filter(|o| o.reason == reason).count()
+        }
+    }
+
+    impl TrackSegment {
+        pub fn clean(self: &mut Self, limits: &Limits, action: Action) -> CleanReport {
+            let outliers = self.find_outliers(limits);
+            let distance_before = self.distance();
+            let mut removed = 0;
+            if action == Action::Remove && !outliers.is_empty() {
+                let mut segment = TrackSegment::new();
+                segment.name = self.name.clone();
+                segment.comment = self.comment.clone();
+                segment.set_distance_model(self.distance_model());
+                segment.set_vertical_datum(self.vertical_datum());
+                let mut next = outliers.iter().map(|o| o.index).peekable();
+                for (i, p) in self.points.iter().enumerate() {
+                    if next.peek() == Some(&i) {
+                        next.next();
+                        removed += 1;
+                    } else {
+                        segment.add_point(p.point.clone());
+                    }
+                }
+                *self = segment;
+            }
+            CleanReport {outliers, removed, distance_before, distance_after: self.distance()}
+        }
+
+        // Outliers in the order of the points
+        fn find_outliers(&self, limits: &Limits) -> Vec<Outlier> {
+            let mut outliers = Vec::new();
+            if self.points.is_empty() {
+                return outliers;
+            }
+            let mut last = 0;
+            while last < ANCHOR_CHECK && last + 1 < self.points.len() && !self.is_anchor(last, limits) {
+                outliers.push(Outlier {index: last, reason: Reason::Speed});
+                last += 1;
+            }
+            let mut last_speed = f64::NAN;
+            for i in last + 1..self.points.len() {
+                let (a, b) = (&self.points[last].point, &self.points[i].point);
+                let next = self.points.get(i + 1).map(|p| &p.point);
+                let mut speed = f64::NAN;
+                let reason = if a.has_time() && b.has_time() {
+                    let dt = seconds(&(b.time - a.time));
+                    if dt <= 0f64 {
+                        Some(Reason::DuplicateTime)
+                    } else {
+                        speed = (a.location.great_circle_distance(&b.location) - limits.position_noise).max(0f64) / dt;
+                        let climb = vertical_rate(a, b, limits).min(vertical_rate(&self.points[i - 1].point, b, limits));
+                        if speed > limits.max_speed {
+                            Some(Reason::Speed)
+                        } else if !last_speed.is_nan() && (speed - last_speed).abs() / dt > limits.max_acceleration {
+                            Some(Reason::Acceleration)
+                        } else if climb > limits.max_vertical_rate {
+                            Some(Reason::VerticalRate)
+                        } else {
+                            None
+                        }
+                    }
+                } else {
+                    None
+                };
+                let reason = reason.or_else(|| {
+                    next.filter(|c| is_zigzag(a, b, c, limits)).map(|_| Reason::ZigZag)
+                });
+                match reason {
+                    Some(reason) => { outliers.push(Outlier {index: i, reason}); }
+                    None => {
+                        last = i;
+                        last_speed = speed;
+                    }
+                }
+            }
+            outliers
+        }
+
+        // The speed from the point to most of the next points with time is within max_speed
+        fn is_anchor(&self, i: usize, limits: &Limits) -> bool {
+            let a = &self.points[i].point;
+            let speeds: Vec<f64> = self.points[i + 1..].iter().take(ANCHOR_CHECK)
+                .map(|p| &p.point)
+                .filter(|b| a.has_time() && b.has_time() && b.time > a.time)
+                .map(|b| {
+                    let dt = seconds(&(b.time - a.time));
+                    (a.location.great_circle_distance(&b.location) - limits.position_noise).max(0f64) / dt
+                })
+                .collect();
+            speeds.iter().filter(|v| **v <= limits.max_speed).count() * 2 >= speeds.len()
+        }
+    }
+
+    // b is a spike between a and c
+    fn is_zigzag(a: &TrackPoint, b: &TrackPoint, c: &TrackPoint, limits: &Limits) -> bool {
+        let (ab, bc) = (a.location.great_circle_distance(&b.location), b.location.great_circle_distance(&c.location));
+        if ab <= limits.zigzag_distance || bc <= limits.zigzag_distance {
+            return false;
+        }
+        let turn = (b.location.initial_bearing(&c.location) - a.location.final_bearing(&b.location)).rem_euclid(360f64);
+        let turn = turn.min(360f64 - turn);
+        turn > limits.zigzag_angle && a.location.great_circle_distance(&c.location) < ab.min(bc)
+    }
+
+    // m/s, 0 when not known
+    fn vertical_rate(a: &TrackPoint, b: &TrackPoint, limits: &Limits) -> f64 {
+        let dt = seconds(&(b.time - a.time));
+        let rate = ((b.altitude - a.altitude).abs() - limits.altitude_noise).max(0f64) / dt;
+        if a.has_time() && dt > 0f64 && !rate.is_nan() { rate } else { 0f64 }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use chrono::{DateTime, Duration, TimeZone, Utc};
+
+        #[test]
+        fn clean() {
+            // Walking north 1.1 m/s every 10 s with a 300m jump, a duplicate time, an altitude spike
+            // and a zig-zag without time
+            let start = Utc.with_ymd_and_hms(2023, 8, 1, 6, 0, 0).unwrap();
+            let mut segment = TrackSegment::new();
+            for i in 0..60 {
+                let mut p = TrackPoint::new(35.0 + i as f64 * 0.0001, 139.0);
+                p.altitude = 1000f64 + i as f64 * 0.5;
+                p.time = start + Duration::seconds(i * 10);
+                match i {
+                    10 => { p.location.lon += 0.0033; }
+                    20 => { p.time = start + Duration::seconds(190); }
+                    30 => { p.altitude += 80f64; }
+                    40 => {
+                        p.location.lon -= 0.003;
+                        p.time = DateTime::<Utc>::MIN_UTC;
+                    }
+                    _ => {}
+                }
+                segment.add_point(p);
+            }
+            let limits = Limits::for_activity(Activity::Hiking);
+            let flagged = segment.clone().clean(&limits, Action::Flag);
+            assert_eq!(flagged.removed, 0);
+            assert_eq!(flagged.distance_after, flagged.distance_before);
+
+            let report = segment.clean(&limits, Action::Remove);
+            let reasons: Vec<(usize, Reason)> = report.outliers.iter().map(|o| (o.index, o.reason)).collect();
+            assert_eq!(reasons, vec![(10, Reason::Speed), (20, Reason::DuplicateTime), (30, Reason::VerticalRate), (40, Reason::ZigZag)]);
+            assert_eq!(report.removed, 4);
+            assert_eq!(segment.points.len(), 56);
+            assert!(report.distance_after < report.distance_before);
+            // Distances are from the remaining neighbours
+            assert!((segment.distance() - 59f64 * 11.1).abs() < 1f64);
+            assert!((segment.points[10].distance() - 22.2).abs() < 0.1);
+
+            // A cold start 300m off, the spike is removed and not the points after it
+            let mut segment = TrackSegment::new();
+            for i in 0..20 {
+                let mut p = TrackPoint::new(35.0 + i as f64 * 0.0001, 139.0);
+                p.time = start + Duration::seconds(i * 10);
+                if i == 0 {
+                    p.location.lon += 0.0033;
+                }
+                segment.add_point(p);
+            }
+            let report = segment.clean(&limits, Action::Remove);
+            let reasons: Vec<(usize, Reason)> = report.outliers.iter().map(|o| (o.index, o.reason)).collect();
+            assert_eq!(reasons, vec![(0, Reason::Speed)]);
+            assert_eq!(segment.points.len(), 19);
+        }
+    }
+}
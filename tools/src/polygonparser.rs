@@ -0,0 +1,223 @@
+pub mod polygon_parser {
+    use std::ffi::OsString;
+    use std::fs::File;
+    use std::io::{BufReader, Read};
+    use std::path::Path;
+    use serde_json::Value;
+    use xml::EventReader;
+    use xml::reader::XmlEvent;
+    use crate::geometry::geometry_core::{LatLon, MultiPolygon, Polygon};
+
+    // Read polygons from .geojson/.json or .kml file
+    pub fn open(name: &OsString) -> Option<MultiPolygon> {
+        let extension = Path::new(name).extension()?.to_str()?.to_ascii_lowercase();
+        let file = match File::open(name) {
+            Ok(x) => { x }
+            Err(e) => {
+                eprintln!("Error on open {:?}: {e}", name);
+                return None;
+            }
+        };
+        match extension.as_str() {
+            "geojson" | "json" => {
+                let mut text = String::new();
+                if let Err(e) = BufReader::new(file).read_to_string(&mut text) {
+                    eprintln!("Error on read {:?}: {e}", name);
+                    return None;
+                }
+                parse_geojson(&text)
+            }
+            "kml" => { parse_kml(BufReader::new(file)) }
+            _ => {
+                eprintln!("{extension} is not supported");
+                None
+            }
+        }
+    }
+
+    // Collect every Polygon and MultiPolygon in the GeoJSON (FeatureCollection, Feature, GeometryCollection or geometry)
+    pub fn parse_geojson(text: &str) -> Option<MultiPolygon> {
+        let json: Value = match serde_json::from_str(text) {
+            Ok(x) => { x }
+            Err(e) => {
+                eprintln!("Error on parse geojson: {e}");
+                return None;
+            }
+        };
+        let mut result = MultiPolygon::new();
+        process_geojson(&json, &mut result)?;
+        Some(result)
+    }
+
+    fn process_geojson(v: &Value, result: &mut MultiPolygon) -> Option<()> {
+        match v.get("type")?.as_str()? {
+            "FeatureCollection" => {
+                for f in v.get("features")?.as_array()? {
+                    process_geojson(f, result)?;
+                }
+            }
+            "Feature" => {
+                match v.get("geometry") {
+                    Some(Value::Null) | None => {}
+                    Some(g) => { process_geojson(g, result)?; }
+                }
+            }
+            "GeometryCollection" => {
+                for g in v.get("geometries")?.as_array()? {
+                    process_geojson(g, result)?;
+                }
+            }
+            "Polygon" => {
+                result.polygons.push(geojson_polygon(v.get("coordinates")?)?);
+            }
+            "MultiPolygon" => {
+                for p in v.get("coordinates")?.as_array()? {
+                    result.polygons.push(geojson_polygon(p)?);
+                }
+            }
+            _ => {}
+        }
+        Some(())
+    }
+
+    fn geojson_polygon(v: &Value) -> Option<Polygon> {
+        let mut rings = v.as_array()?.iter();
+        let mut polygon = Polygon::new(geojson_ring(rings.next()?)?);
+        for r in rings {
+            polygon.add_hole(geojson_ring(r)?);
+        }
+        Some(polygon)
+    }
+
+    // Position is [longitude, latitude(, altitude)]
+    fn geojson_ring(v: &Value) -> Option<Vec<LatLon>> {
+        let mut ring = Vec::new();
+        for p in v.as_array()? {
+            let p = p.as_array()?;
+            ring.push(LatLon::new(p.get(1)?.as_f64()?, p.first()?.as_f64()?));
+        }
+        Some(ring)
+    }
+
+    // Collect every <Polygon> in the KML, outerBoundaryIs is the exterior and innerBoundaryIs are the holes
+    pub fn parse_kml<R: Read>(reader: R) -> Option<MultiPolygon> {
+        let mut parser = EventReader::new(reader);
+        let mut result = MultiPolygon::new();
+        let mut polygon: Option<Polygon> = None;
+        let mut inner = false;
+        let mut coordinates = String::new();
+        let mut in_coordinates = false;
+        loop {
+            match parser.next() {
+                Ok(XmlEvent::StartElement { name, .. }) => {
+                    match name.local_name.as_str() {
+                        "Polygon" => { polygon = Some(Polygon::new(Vec::new())); }
+                        "outerBoundaryIs" => { inner = false; }
+                        "innerBoundaryIs" => { inner = true; }
+                        "coordinates" => {
+                            in_coordinates = true;
+                            coordinates.clear();
+                        }
+                        _ => {}
+                    }
+                }
+                Ok(XmlEvent::Characters(str)) if in_coordinates => {
+                    coordinates.push_str(&str);
+                }
+                Ok(XmlEvent::EndElement { name }) => {
+                    match name.local_name.as_str() {
+                        "coordinates" => {
+                            in_coordinates = false;
+                            if let Some(p) = polygon.as_mut() {
+                                let ring = kml_ring(&coordinates)?;
+                                if inner { p.add_hole(ring); } else { p.exterior = ring; }
+                            }
+                        }
+                        "Polygon" => {
+                            if let Some(p) = polygon.take() {
+                                result.polygons.push(p);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Ok(XmlEvent::EndDocument) => { break; }
+                Err(e) => {
+                    eprintln!("Error on parse kml: {e}");
+                    return None;
+                }
+                _ => {}
+            }
+        }
+        Some(result)
+    }
+
+    // Tuples of "longitude,latitude[,altitude]" separated by white spaces
+    fn kml_ring(text: &str) -> Option<Vec<LatLon>> {
+        let mut ring = Vec::new();
+        for t in text.split_whitespace() {
+            let v: Vec<&str> = t.split(',').collect();
+            if v.len() < 2 {
+                eprintln!("Invalid coordinates: {t}");
+                return None;
+            }
+            ring.push(LatLon::new(v[1].parse().ok()?, v[0].parse().ok()?));
+        }
+        Some(ring)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn geojson() {
+            // 1 x 1 degree square with a 0.2 degree hole, and a feature with a MultiPolygon of two squares
+            let text = r#"{"type": "FeatureCollection", "features": [
+                {"type": "Feature", "properties": {}, "geometry": {"type": "Polygon", "coordinates": [
+                    [[139.0, 35.0], [140.0, 35.0], [140.0, 36.0], [139.0, 36.0], [139.0, 35.0]],
+                    [[139.4, 35.4], [139.6, 35.4], [139.6, 35.6], [139.4, 35.6], [139.4, 35.4]]]}},
+                {"type": "Feature", "properties": {}, "geometry": null},
+                {"type": "Feature", "properties": {}, "geometry": {"type": "MultiPolygon", "coordinates": [
+                    [[[141.0, 35.0, 10.0], [141.1, 35.0, 10.0], [141.1, 35.1, 10.0], [141.0, 35.0, 10.0]]],
+                    [[[142.0, 35.0], [142.1, 35.0], [142.1, 35.1], [142.0, 35.0]]]]}}]}"#;
+            let m = parse_geojson(text).unwrap();
+            assert_eq!(m.polygons.len(), 3);
+            assert_eq!(m.polygons[0].holes.len(), 1);
+            assert!(m.polygons[0].contains(&LatLon::new(35.2, 139.2)));
+            assert!(!m.polygons[0].contains(&LatLon::new(35.5, 139.5)));
+            assert!(m.contains(&LatLon::new(35.02, 142.08)));
+            assert_eq!(m.polygons[1].exterior[1].lon, 141.1);
+
+            // Malformed coordinates and JSON
+            assert!(parse_geojson(r#"{"type": "Polygon", "coordinates": [[[139.0], [140.0, 35.0], [139.0, 35.0]]]}"#).is_none());
+            assert!(parse_geojson(r#"{"type": "Polygon", "coordinates": [[["139.0", 35.0]]]}"#).is_none());
+            assert!(parse_geojson(r#"{"type": "Polygon", "coordinates": "#).is_none());
+        }
+
+        #[test]
+        fn kml() {
+            let text = r#"<?xml version="1.0" encoding="UTF-8"?>
+                <kml xmlns="http://www.opengis.net/kml/2.2"><Document><Placemark><Polygon>
+                  <outerBoundaryIs><LinearRing><coordinates>
+                    139.0,35.0,0 140.0,35.0,0 140.0,36.0,0
+                    139.0,36.0,0 139.0,35.0,0
+                  </coordinates></LinearRing></outerBoundaryIs>
+                  <innerBoundaryIs><LinearRing><coordinates>139.4,35.4 139.6,35.4 139.6,35.6 139.4,35.6 139.4,35.4</coordinates></LinearRing></innerBoundaryIs>
+                </Polygon></Placemark></Document></kml>"#;
+            let m = parse_kml(text.as_bytes()).unwrap();
+            assert_eq!(m.polygons.len(), 1);
+            assert_eq!(m.polygons[0].exterior.len(), 5);
+            assert_eq!(m.polygons[0].holes.len(), 1);
+            assert!(m.contains(&LatLon::new(35.2, 139.2)));
+            assert!(!m.contains(&LatLon::new(35.5, 139.5)));
+
+            // A tuple without latitude, and a value not a number
+            let broken = text.replace("139.4,35.4 139.6", "139.4 139.6");
+            assert!(parse_kml(broken.as_bytes()).is_none());
+            let broken = text.replace("140.0,36.0,0", "140.0,N36,0");
+            assert!(parse_kml(broken.as_bytes()).is_none());
+            assert!(parse_kml("<kml><Polygon>".as_bytes()).is_none());
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//
+// Position lookup tools
+//
+// [position_at] the point on the track at a time, clock_offset is how far the other clock (a camera) runs ahead
+//
+// The point is interpolated on the leg as resample does, between the nearest points with time.
+// Points without time between them are placed by the distance along the track.
+// None before the first or after the last point with time, and when the time falls in a segment gap.
+//
+pub mod position {
+    use chrono::{DateTime, Duration, Utc};
+    use crate::gpx::gpx::{seconds, Track, TrackPoint, TrackSegment};
+
+    impl TrackSegment {
+        pub fn position_at(&self, time: DateTime<Utc>, clock_offset: Duration) -> Option<TrackPoint> {
+            let t = time - clock_offset;
+            let known: Vec<usize> = (0..self.points.len()).filter(|i| self.points[*i].point.has_time()).collect();
+            for w in known.windows(2) {
+                let (a, b) = (w[0], w[1]);
+                let (t1, t2) = (self.points[a].point.time, self.points[b].point.time);
+                if t2 <= t1 || t < t1 || t > t2 {
+                    continue;
+                }
+                let f = seconds(&(t - t1)) / seconds(&(t2 - t1));
+                // Distance from a over the points without time
+                let leg: f64 = self.points[a + 1..=b].iter().map(|p| p.distance()).sum();
+                let mut d = leg * f;
+                let mut i = a + 1;
+                while i < b && d > self.points[i].distance() {
+                    d -= self.points[i].distance();
+                    i += 1;
+                }
+                let g = if b == a + 1 {
+                    f
+                } else if self.points[i].distance() > 0f64 {
+                    (d / self.points[i].distance()).clamp(0f64, 1f64)
+                } else {
+                    1f64
+                };
+                let mut p = self.interpolate(i, g);
+                p.time = t;
+                return Some(p);
+            }
+            None
+        }
+    }
+
+    impl Track {
+        pub fn position_at(&self, time: DateTime<Utc>, clock_offset: Duration) -> Option<TrackPoint> {
+            self.routes.iter().flat_map(|r| r.segments.iter()).find_map(|s| s.position_at(time, clock_offset))
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use chrono::TimeZone;
+        use crate::gpx::gpx::TrackRoute;
+
+        #[test]
+        fn position_at() {
+            // 5 points toward north every 60 s and 66.7m, the point 2 has no time, the point 3 has a name
+            let start = Utc.with_ymd_and_hms(2023, 8, 1, 6, 0, 0).unwrap();
+            let mut segment = TrackSegment::new();
+            for i in 0..5 {
+                let mut p = TrackPoint::new(35.0 + i as f64 * 0.0006, 139.0);
+                if i != 2 {
+                    p.time = start + Duration::seconds(i * 60);
+                }
+                p.altitude = 1000f64 + i as f64 * 10f64;
+                if i == 3 {
+                    p.name = String::from("Junction");
+                }
+                segment.add_point(p);
+            }
+
+            // Camera 2 minutes ahead of the GPS
+            let p = segment.position_at(start + Duration::seconds(150), Duration::minutes(2)).unwrap();
+            assert_eq!(p.time, start + Duration::seconds(30));
+            assert!((p.location.lat - 35.0003).abs() < 1e-9);
+            assert!((p.altitude - 1005f64).abs() < 1e-9);
+
+            // Exactly on a point, the point without its name
+            let p = segment.position_at(start + Duration::seconds(180), Duration::zero()).unwrap();
+            assert_eq!(p.time, start + Duration::seconds(180));
+            assert!((p.location.lat - 35.0018).abs() < 1e-9);
+            assert_eq!(p.altitude, 1030f64);
+            assert!(p.name.is_empty());
+            let p = segment.position_at(start, Duration::zero()).unwrap();
+            assert_eq!((p.location.lat, p.altitude), (35.0, 1000f64));
+
+            // Across the point without time, by the distance
+            let p = segment.position_at(start + Duration::seconds(120), Duration::zero()).unwrap();
+            assert_eq!(p.time, start + Duration::seconds(120));
+            assert!((p.location.lat - 35.0012).abs() < 1e-9);
+            assert!((p.altitude - 1020f64).abs() < 1e-3);
+            let p = segment.position_at(start + Duration::seconds(75), Duration::zero()).unwrap();
+            assert!((p.location.lat - 35.00075).abs() < 1e-9);
+
+            // Outside of the recorded time
+            assert!(segment.position_at(start - Duration::seconds(1), Duration::zero()).is_none());
+            assert!(segment.position_at(start + Duration::seconds(241), Duration::zero()).is_none());
+            assert!(TrackSegment::new().position_at(start, Duration::zero()).is_none());
+
+            // The segment with the time in the track
+            let mut later = TrackSegment::new();
+            for i in 0..2 {
+                let mut p = TrackPoint::new(36.0 + i as f64 * 0.001, 139.0);
+                p.time = start + Duration::hours(1) + Duration::seconds(i * 60);
+                later.add_point(p);
+            }
+            let mut route = TrackRoute::new();
+            route.add_segment(&segment);
+            route.add_segment(&later);
+            let mut track = Track::new();
+            track.routes.push(route);
+            let p = track.position_at(start + Duration::seconds(3630), Duration::zero()).unwrap();
+            assert!((p.location.lat - 36.0005).abs() < 1e-9);
+            assert!(track.position_at(start + Duration::minutes(30), Duration::zero()).is_none());
+        }
+    }
+}
@@ -0,0 +1,337 @@
+
+//
+// Map projection tools
+//
+// Transverse Mercator by the Krüger series (6th order of n), the same formulae GSI uses for
+// the Japan Plane Rectangular Coordinate System.
+//
+// -- Coordinate system --
+// [UTM] zone: 1 ~ 60, easting with 500km false easting, northing with 10000km false northing on the southern hemisphere
+// [JPRCS] zone: 1 ~ 19 (I ~ XIX), easting = Y, northing = X in the Japanese survey convention
+//
+pub mod projection {
+    use std::fmt;
+    use crate::geodesic::geodesic::Ellipsoid;
+    use crate::geometry::geometry_core::LatLon;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Zone {
+        Utm { zone: u8, north: bool },
+        Jprcs(u8),
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    pub struct ProjectedCoord {
+        pub zone: Zone,
+        pub easting: f64,
+        pub northing: f64,
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    pub struct TransverseMercator {
+        pub ellipsoid: Ellipsoid,
+        pub origin: LatLon,
+        pub scale: f64,
+        pub false_easting: f64,
+        pub false_northing: f64,
+    }
+
+    // Origin (latitude, longitude) of JPRCS zone I ~ XIX in degree and minute
+    const JPRCS_ORIGIN: [((f64, f64), (f64, f64)); 19] = [
+        ((33f64, 0f64), (129f64, 30f64)),
+        ((33f64, 0f64), (131f64, 0f64)),
+        ((36f64, 0f64), (132f64, 10f64)),
+        ((33f64, 0f64), (133f64, 30f64)),
+        ((36f64, 0f64), (134f64, 20f64)),
+        ((36f64, 0f64), (136f64, 0f64)),
+        ((36f64, 0f64), (137f64, 10f64)),
+        ((36f64, 0f64), (138f64, 30f64)),
+        ((36f64, 0f64), (139f64, 50f64)),
+        ((40f64, 0f64), (140f64, 50f64)),
+        ((44f64, 0f64), (140f64, 15f64)),
+        ((44f64, 0f64), (142f64, 15f64)),
+        ((44f64, 0f64), (144f64, 15f64)),
+        ((26f64, 0f64), (142f64, 0f64)),
+        ((26f64, 0f64), (127f64, 30f64)),
+        ((26f64, 0f64), (124f64, 0f64)),
+        ((26f64, 0f64), (131f64, 0f64)),
+        ((20f64, 0f64), (136f64, 0f64)),
+        ((26f64, 0f64), (154f64, 0f64)),
+    ];
+
+    const ROMAN: [&str; 19] = [
+        "I", "II", "III", "IV", "V", "VI", "VII", "VIII", "IX", "X",
+        "XI", "XII", "XIII", "XIV", "XV", "XVI", "XVII", "XVIII", "XIX",
+    ];
+
+    impl Zone {
+        // UTM zone containing the location, including the Norway and Svalbard exceptions
+        pub fn utm_for(l: &LatLon) -> Zone {
+            let lon = if l.lon >= 180f64 { l.lon - 360f64 } else { l.lon };
+            let mut zone = ((lon + 180f64) / 6f64).floor() as u8 + 1;
+            if (56f64..64f64).contains(&l.lat) && (3f64..12f64).contains(&lon) {
+                zone = 32;
+            }
+            if (72f64..84f64).contains(&l.lat) && (0f64..42f64).contains(&lon) {
+                zone = match lon {
+                    x if x < 9f64 => 31,
+                    x if x < 21f64 => 33,
+                    x if x < 33f64 => 35,
+                    _ => 37,
+                };
+            }
+            Zone::Utm { zone: zone.min(60), north: l.lat >= 0f64 }
+        }
+
+        // JPRCS zone whose origin is nearest to the location, not the legal zone.
+        // The legal zone is assigned per prefecture/municipality, this matches it for the main cities
+        // but can differ near the zone borders.
+        pub fn nearest_jprcs(l: &LatLon) -> Zone {
+            let mut best = 1u8;
+            let mut min = f64::MAX;
+            for z in 1..=19u8 {
+                let d = l.great_circle_distance(&Zone::Jprcs(z).origin());
+                if d < min {
+                    min = d;
+                    best = z;
+                }
+            }
+            Zone::Jprcs(best)
+        }
+
+        pub fn origin(&self) -> LatLon {
+            match self {
+                Zone::Utm { zone, .. } => { LatLon::new(0f64, *zone as f64 * 6f64 - 183f64) }
+                Zone::Jprcs(z) => {
+                    let ((lat_d, lat_m), (lon_d, lon_m)) = JPRCS_ORIGIN[(*z as usize).clamp(1, 19) - 1];
+                    LatLon::new(lat_d + lat_m / 60f64, lon_d + lon_m / 60f64)
+                }
+            }
+        }
+
+        pub fn projection(&self) -> TransverseMercator {
+            match self {
+                Zone::Utm { north, .. } => {
+                    TransverseMercator {
+                        ellipsoid: Ellipsoid::WGS84,
+                        origin: self.origin(),
+                        scale: 0.9996,
+                        false_easting: 500000f64,
+                        false_northing: if *north { 0f64 } else { 10000000f64 },
+                    }
+                }
+                Zone::Jprcs(_) => {
+                    TransverseMercator {
+                        ellipsoid: Ellipsoid::GRS80,
+                        origin: self.origin(),
+                        scale: 0.9999,
+                        false_easting: 0f64,
+                        false_northing: 0f64,
+                    }
+                }
+            }
+        }
+    }
+
+    impl fmt::Display for Zone {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                Zone::Utm { zone, north } => { write!(f, "UTM {}{}", zone, if *north { "N" } else { "S" }) }
+                Zone::Jprcs(z) => { write!(f, "JPRCS {}", ROMAN[(*z as usize).clamp(1, 19) - 1]) }
+            }
+        }
+    }
+
+    impl ProjectedCoord {
+        pub fn from_latlon(l: &LatLon, zone: Zone) -> ProjectedCoord {
+            let (easting, northing) = zone.projection().forward(l);
+            ProjectedCoord { zone, easting, northing }
+        }
+
+        pub fn utm(l: &LatLon) -> ProjectedCoord {
+            Self::from_latlon(l, Zone::utm_for(l))
+        }
+
+        // In the zone of Zone::nearest_jprcs
+        pub fn nearest_jprcs(l: &LatLon) -> ProjectedCoord {
+            Self::from_latlon(l, Zone::nearest_jprcs(l))
+        }
+
+        pub fn latlon(&self) -> LatLon {
+            self.zone.projection().inverse(self.easting, self.northing)
+        }
+    }
+
+    impl fmt::Display for ProjectedCoord {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "({} E:{:.3}m, N:{:.3}m)", self.zone, self.easting, self.northing)
+        }
+    }
+
+    impl TransverseMercator {
+        // Third flattening n and the rectifying radius A multiplied by the scale
+        fn radius(&self) -> (f64, f64) {
+            let f = self.ellipsoid.f;
+            let n = f / (2f64 - f);
+            let n2 = n * n;
+            let a = self.ellipsoid.a / (1f64 + n) * (1f64 + n2 / 4f64 + n2 * n2 / 64f64 + n2 * n2 * n2 / 256f64);
+            (n, self.scale * a)
+        }
+
+        fn alpha(n: f64) -> [f64; 6] {
+            let n2 = n * n;
+            let n3 = n2 * n;
+            let n4 = n3 * n;
+            let n5 = n4 * n;
+            let n6 = n5 * n;
+            [
+                n / 2f64 - 2f64 * n2 / 3f64 + 5f64 * n3 / 16f64 + 41f64 * n4 / 180f64 - 127f64 * n5 / 288f64 + 7891f64 * n6 / 37800f64,
+                13f64 * n2 / 48f64 - 3f64 * n3 / 5f64 + 557f64 * n4 / 1440f64 + 281f64 * n5 / 630f64 - 1983433f64 * n6 / 1935360f64,
+                61f64 * n3 / 240f64 - 103f64 * n4 / 140f64 + 15061f64 * n5 / 26880f64 + 167603f64 * n6 / 181440f64,
+                49561f64 * n4 / 161280f64 - 179f64 * n5 / 168f64 + 6601661f64 * n6 / 7257600f64,
+                34729f64 * n5 / 80640f64 - 3418889f64 * n6 / 1995840f64,
+                212378941f64 * n6 / 319334400f64,
+            ]
+        }
+
+        fn beta(n: f64) -> [f64; 6] {
+            let n2 = n * n;
+            let n3 = n2 * n;
+            let n4 = n3 * n;
+            let n5 = n4 * n;
+            let n6 = n5 * n;
+            [
+                n / 2f64 - 2f64 * n2 / 3f64 + 37f64 * n3 / 96f64 - n4 / 360f64 - 81f64 * n5 / 512f64 + 96199f64 * n6 / 604800f64,
+                n2 / 48f64 + n3 / 15f64 - 437f64 * n4 / 1440f64 + 46f64 * n5 / 105f64 - 1118711f64 * n6 / 3870720f64,
+                17f64 * n3 / 480f64 - 37f64 * n4 / 840f64 - 209f64 * n5 / 4480f64 + 5569f64 * n6 / 90720f64,
+                4397f64 * n4 / 161280f64 - 11f64 * n5 / 504f64 - 830251f64 * n6 / 7257600f64,
+                4583f64 * n5 / 161280f64 - 108847f64 * n6 / 3991680f64,
+                20648693f64 * n6 / 638668800f64,
+            ]
+        }
+
+        // Conformal latitude -> latitude
+        fn delta(n: f64) -> [f64; 6] {
+            let n2 = n * n;
+            let n3 = n2 * n;
+            let n4 = n3 * n;
+            let n5 = n4 * n;
+            let n6 = n5 * n;
+            [
+                2f64 * n - 2f64 * n2 / 3f64 - 2f64 * n3 + 116f64 * n4 / 45f64 + 26f64 * n5 / 45f64 - 2854f64 * n6 / 675f64,
+                7f64 * n2 / 3f64 - 8f64 * n3 / 5f64 - 227f64 * n4 / 45f64 + 2704f64 * n5 / 315f64 + 2323f64 * n6 / 945f64,
+                56f64 * n3 / 15f64 - 136f64 * n4 / 35f64 - 1262f64 * n5 / 105f64 + 73814f64 * n6 / 2835f64,
+                4279f64 * n4 / 630f64 - 332f64 * n5 / 35f64 - 399572f64 * n6 / 14175f64,
+                4174f64 * n5 / 315f64 - 144838f64 * n6 / 6237f64,
+                601676f64 * n6 / 22275f64,
+            ]
+        }
+
+        // (xi', eta') on the Gauss-Schreiber sphere
+        fn conformal(&self, n: f64, l: &LatLon) -> (f64, f64) {
+            let (phi, lambda) = l.radian();
+            let (_, lambda0) = self.origin.radian();
+            let e = 2f64 * n.sqrt() / (1f64 + n);
+            let t = (phi.sin().atanh() - e * (e * phi.sin()).atanh()).sinh();
+            let dl = lambda - lambda0;
+            let xi = t.atan2(dl.cos());
+            let eta = (dl.sin() / (1f64 + t * t).sqrt()).atanh();
+            (xi, eta)
+        }
+
+        // Meridian arc from the equator to the origin latitude
+        fn origin_northing(&self, n: f64, a: f64) -> f64 {
+            let (xi0, _) = self.conformal(n, &LatLon::new(self.origin.lat, self.origin.lon));
+            let alpha = Self::alpha(n);
+            let mut s = xi0;
+            for (j, al) in alpha.iter().enumerate() {
+                s += al * (2f64 * (j + 1) as f64 * xi0).sin();
+            }
+            a * s
+        }
+
+        // Returns (easting, northing) in metre
+        pub fn forward(&self, l: &LatLon) -> (f64, f64) {
+            let (n, a) = self.radius();
+            let (xi, eta) = self.conformal(n, l);
+            let alpha = Self::alpha(n);
+            let mut x = xi;
+            let mut y = eta;
+            for (j, al) in alpha.iter().enumerate() {
+                let k = 2f64 * (j + 1) as f64;
+                x += al * (k * xi).sin() * (k * eta).cosh();
+                y += al * (k * xi).cos() * (k * eta).sinh();
+            }
+            let northing = a * x - self.origin_northing(n, a) + self.false_northing;
+            let easting = a * y + self.false_easting;
+            (easting, northing)
+        }
+
+        pub fn inverse(&self, easting: f64, northing: f64) -> LatLon {
+            let (n, a) = self.radius();
+            let xi = (northing - self.false_northing + self.origin_northing(n, a)) / a;
+            let eta = (easting - self.false_easting) / a;
+            let beta = Self::beta(n);
+            let mut xi2 = xi;
+            let mut eta2 = eta;
+            for (j, be) in beta.iter().enumerate() {
+                let k = 2f64 * (j + 1) as f64;
+                xi2 -= be * (k * xi).sin() * (k * eta).cosh();
+                eta2 -= be * (k * xi).cos() * (k * eta).sinh();
+            }
+            let chi = (xi2.sin() / eta2.cosh()).asin();
+            let mut phi = chi;
+            for (j, de) in Self::delta(n).iter().enumerate() {
+                phi += de * (2f64 * (j + 1) as f64 * chi).sin();
+            }
+            let lambda = self.origin.radian().1 + eta2.sinh().atan2(xi2.cos());
+            LatLon::new(phi.to_degrees(), lambda.to_degrees())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn dms(d: f64, m: f64, s: f64) -> f64 {
+            d + m / 60f64 + s / 3600f64
+        }
+
+        // GSI headquarters in Tsukuba, zone IX. The sample of GSI's conversion formulae
+        // (X = 11543.6883m, Y = 22916.2436m)
+        #[test]
+        fn jprcs_tsukuba() {
+            let l = LatLon::new(dms(36f64, 6f64, 13.58925), dms(140f64, 5f64, 16.27815));
+            let p = ProjectedCoord::from_latlon(&l, Zone::Jprcs(9));
+            assert!((p.northing - 11543.6883).abs() < 1e-3);
+            assert!((p.easting - 22916.2436).abs() < 1e-3);
+            let r = p.latlon();
+            assert!((r.lat - l.lat).abs() < 1e-9 && (r.lon - l.lon).abs() < 1e-9);
+        }
+
+        #[test]
+        fn jprcs_zone_selection() {
+            assert_eq!(Zone::nearest_jprcs(&LatLon::new(35.681236, 139.767125)), Zone::Jprcs(9));  // Tokyo
+            assert_eq!(Zone::nearest_jprcs(&LatLon::new(34.702485, 135.495951)), Zone::Jprcs(6));  // Osaka
+            assert_eq!(Zone::nearest_jprcs(&LatLon::new(43.068661, 141.350755)), Zone::Jprcs(12)); // Sapporo
+            assert_eq!(Zone::nearest_jprcs(&LatLon::new(26.212401, 127.680932)), Zone::Jprcs(15)); // Naha
+        }
+
+        #[test]
+        fn utm_round_trip() {
+            let l = LatLon::new(35.360556, 138.727778);  // Mt. Fuji
+            let p = ProjectedCoord::utm(&l);
+            assert_eq!(p.zone, Zone::Utm { zone: 54, north: true });
+            assert!((p.easting - 293551.5850).abs() < 1e-3);
+            assert!((p.northing - 3915398.3471).abs() < 1e-3);
+            let r = p.latlon();
+            assert!((r.lat - l.lat).abs() < 1e-9 && (r.lon - l.lon).abs() < 1e-9);
+
+            let l = LatLon::new(-33.856784, 151.215297);  // Sydney
+            let p = ProjectedCoord::utm(&l);
+            assert_eq!(p.zone, Zone::Utm { zone: 56, north: false });
+            let r = p.latlon();
+            assert!((r.lat - l.lat).abs() < 1e-9 && (r.lon - l.lon).abs() < 1e-9);
+        }
+    }
+}
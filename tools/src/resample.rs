@@ -0,0 +1,225 @@
+//
+// Track resampling tools
+//
+// -- Interval --
+// [Time] every given duration from the first point with time, legs without time are skipped
+// [Distance] every given distance (m) along the track as TrackSegment::distance
+// The last point is always kept.
+//
+// Location, altitude, time and the sensor values are interpolated on the leg, NaN when either end has no value.
+// Heading and wind direction take the shorter turn. Weather and wind strength carry over from the previous point.
+// Names, comments, links and point types move to the nearest resampled point, joined when two land on the same one.
+//
+pub mod resample {
+    use chrono::{DateTime, Duration, Utc};
+    use crate::gpx::gpx::{is_known_time, seconds, PointType, TrackPoint, TrackSegment};
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Interval {
+        Time(Duration),
+        Distance(f64),
+    }
+
+    impl TrackSegment {
+        // None when the interval is not positive, or no leg has time for Interval::Time
+        pub fn resample(&self, interval: Interval) -> Option<TrackSegment> {
+            let n = self.points.len();
+            if n < 2 {
+                return None;
+            }
+            let mut along = Vec::with_capacity(n);
+            let mut d = 0f64;
+            for p in &self.points {
+                d += p.distance();
+                along.push(d);
+            }
+
+            // Samples as (i, f): fraction f on the leg from the point i - 1 to i
+            let mut samples: Vec<(usize, f64)> = Vec::new();
+            match interval {
+                Interval::Distance(step) => {
+                    if step <= 0f64 {
+                        return None;
+                    }
+                    let mut d = 0f64;
+                    while d < along[n - 1] {
+                        let i = along.partition_point(|a| *a < d).clamp(1, n - 1);
+                        let leg = along[i] - along[i - 1];
+                        samples.push((i, if leg > 0f64 { ((d - along[i - 1]) / leg).clamp(0f64, 1f64) } else { 1f64 }));
+                        d += step;
+                    }
+                }
+                Interval::Time(step) => {
+                    let step = seconds(&step);
+                    let t0 = self.points.iter().map(|p| p.point.time).find(is_known_time)?;
+                    if step <= 0f64 {
+                        return None;
+                    }
+                    let time: Vec<f64> = self.points.iter().map(|p| {
+                        if p.point.has_time() { seconds(&(p.point.time - t0)) } else { f64::NAN }
+                    }).collect();
+                    let mut t = 0f64;
+                    for i in 1..n {
+                        let (t1, t2) = (time[i - 1], time[i]);
+                        if t1.is_nan() || t2.is_nan() || t2 <= t1 {
+                            continue;
+                        }
+                        while t < t1 {
+                            t += step;
+                        }
+                        while t < t2 {
+                            samples.push((i, (t - t1) / (t2 - t1)));
+                            t += step;
+                        }
+                    }
+                    if samples.is_empty() {
+                        return None;
+                    }
+                }
+            }
+            samples.push((n - 1, 1f64));
+
+            let mut segment = TrackSegment::new();
+            segment.name = self.name.clone();
+            segment.comment = self.comment.clone();
+            segment.set_distance_model(self.distance_model());
+            segment.set_vertical_datum(self.vertical_datum());
+            let mut points: Vec<TrackPoint> = samples.iter().map(|(i, f)| self.interpolate(*i, *f)).collect();
+
+            // Named points to the nearest sample along the track
+            let sample_along: Vec<f64> = samples.iter().map(|(i, f)| along[i - 1] + (along[*i] - along[i - 1]) * f).collect();
+            for (k, p) in self.points.iter().enumerate() {
+                let p = &p.point;
+                let types: Vec<PointType> = p.point_type.iter().copied().filter(|t| *t != PointType::None).collect();
+                if p.name.is_empty() && p.comment.is_empty() && p.link.is_empty() && types.is_empty() {
+                    continue;
+                }
+                let j = sample_along.partition_point(|a| *a < along[k]).min(points.len() - 1);
+                let j = if j > 0 && along[k] - sample_along[j - 1] < sample_along[j] - along[k] { j - 1 } else { j };
+                let q = &mut points[j];
+                join(&mut q.name, &p.name);
+                join(&mut q.comment, &p.comment);
+                if q.link.is_empty() {
+                    q.link = p.link.clone();
+                }
+                q.point_type.retain(|t| *t != PointType::None);
+                for t in types {
+                    if !q.point_type.contains(&t) {
+                        q.point_type.push(t);
+                    }
+                }
+                if q.point_type.is_empty() {
+                    q.point_type.push(PointType::None);
+                }
+            }
+            for p in points {
+                segment.add_point(p);
+            }
+            Some(segment)
+        }
+
+        // Point at the fraction f on the leg from the point i - 1 to i, without name, comment, link and point type
+        pub(crate) fn interpolate(&self, i: usize, f: f64) -> TrackPoint {
+            let (a, b) = (&self.points[i - 1].point, &self.points[i].point);
+            let mut p = if f < 1f64 { a.clone() } else { b.clone() };
+            p.location = a.location.intermediate(&b.location, f);
+            p.altitude = lerp(a.altitude, b.altitude, f);
+            p.time = if f == 0f64 {
+                a.time
+            } else if f == 1f64 {
+                b.time
+            } else if a.has_time() && b.has_time() {
+                a.time + Duration::milliseconds(((b.time - a.time).num_milliseconds() as f64 * f).round() as i64)
+            } else {
+                DateTime::<Utc>::MIN_UTC
+            };
+            p.heading = lerp_angle(a.heading, b.heading, f);
+            p.pressure = lerp(a.pressure, b.pressure, f);
+            p.temperature = lerp(a.temperature, b.temperature, f);
+            p.heart_rate = lerp(a.heart_rate, b.heart_rate, f);
+            p.luminance = lerp(a.luminance, b.luminance, f);
+            p.radiation = lerp(a.radiation, b.radiation, f);
+            p.distance = lerp(a.distance, b.distance, f);
+            p.energy = lerp(a.energy, b.energy, f);
+            p.cadence = lerp(a.cadence, b.cadence, f);
+            p.pace = lerp(a.pace, b.pace, f);
+            p.vertical_speed = lerp(a.vertical_speed, b.vertical_speed, f);
+            p.wind.direction = lerp_angle(a.wind.direction, b.wind.direction, f);
+            p.name = String::new();
+            p.comment = String::new();
+            p.link = String::new();
+            p.point_type = vec![PointType::None];
+            p
+        }
+    }
+
+    // The ends are taken as they are, so a value is not lost on the points
+    fn lerp(a: f64, b: f64, f: f64) -> f64 {
+        if f == 0f64 { a } else if f == 1f64 { b } else { a + (b - a) * f }
+    }
+
+    // Degrees, the shorter turn from a to b
+    fn lerp_angle(a: f64, b: f64, f: f64) -> f64 {
+        let turn = (b - a + 540f64).rem_euclid(360f64) - 180f64;
+        if f == 0f64 { a } else if f == 1f64 { b } else { (a + turn * f).rem_euclid(360f64) }
+    }
+
+    fn join(to: &mut String, s: &str) {
+        if s.is_empty() || to == s {
+            return;
+        }
+        if !to.is_empty() {
+            to.push_str(", ");
+        }
+        to.push_str(s);
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use chrono::TimeZone;
+        use crate::gpx::gpx::Weather;
+
+        #[test]
+        fn resample() {
+            // 20 points toward north every 60 s and 66.7m, heart rate and heading changing
+            let start = Utc.with_ymd_and_hms(2023, 8, 1, 6, 0, 0).unwrap();
+            let mut segment = TrackSegment::new();
+            for i in 0..20 {
+                let mut p = TrackPoint::new(35.0 + i as f64 * 0.0006, 139.0);
+                p.time = start + Duration::seconds(i * 60);
+                p.altitude = 1000f64 + i as f64 * 6f64;
+                p.heart_rate = 100f64 + i as f64 * 3f64;
+                p.heading = if i % 2 == 0 { 350f64 } else { 10f64 };
+                p.weather = Weather(if i < 10 { Weather::SUNNY } else { Weather::RAIN });
+                if i == 7 {
+                    p.name = String::from("Junction");
+                    p.point_type = vec![PointType::Node];
+                }
+                segment.add_point(p);
+            }
+
+            let by_time = segment.resample(Interval::Time(Duration::seconds(15))).unwrap();
+            assert_eq!(by_time.points.len(), 19 * 4 + 1);
+            let p = &by_time.points[2].point;
+            assert_eq!(p.time, start + Duration::seconds(30));
+            assert!((p.altitude - 1003f64).abs() < 1e-9);
+            assert!((p.heart_rate - 101.5).abs() < 1e-9);
+            assert!(p.heading.abs() < 1e-9 || (p.heading - 360f64).abs() < 1e-9);
+            assert!(by_time.points[39].point.weather == Weather(Weather::SUNNY));
+            assert!(by_time.points[41].point.weather == Weather(Weather::RAIN));
+            assert_eq!(by_time.points.iter().filter(|p| p.point.name == "Junction").count(), 1);
+            assert!(by_time.points[28].point.point_type == vec![PointType::Node]);
+            assert!((by_time.distance() - segment.distance()).abs() < 0.01);
+
+            let by_distance = segment.resample(Interval::Distance(100f64)).unwrap();
+            let total = segment.distance();
+            assert_eq!(by_distance.points.len(), (total / 100f64).ceil() as usize + 1);
+            assert!((by_distance.points[1].distance() - 100f64).abs() < 0.01);
+            assert_eq!(by_distance.points.last().unwrap().point.time, start + Duration::seconds(19 * 60));
+
+            assert!(segment.resample(Interval::Distance(0f64)).is_none());
+            assert!(TrackSegment::new().resample(Interval::Distance(10f64)).is_none());
+        }
+    }
+}
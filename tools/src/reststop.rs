@@ -0,0 +1,213 @@
+//
+// Rest stop detection tools
+//
+// A rest stop is a run of points staying within the radius (m) of their centroid for min_duration or longer.
+// Points without time break a run. The centroid is the average of the latitudes and longitudes.
+//
+// -- Waypoint --
+// [Name] the nearest known waypoint (named) within name_radius (m), "Rest N" otherwise
+// [Type] Hut / Summit of the known waypoint, Summit when no point within summit_range (m) along the track
+//        is higher than the stop by more than summit_tolerance (m), Note for the others
+//
+pub mod rest_stop {
+    use chrono::{DateTime, Duration, Utc};
+    use crate::geometry::geometry_core::LatLon;
+    use crate::gpx::gpx::{PointType, Track, TrackPoint, TrackSegment};
+
+    #[derive(Debug, Clone)]
+    pub struct RestStop {
+        pub start: usize,               // Point index in the segment
+        pub end: usize,
+        pub arrival: DateTime<Utc>,
+        pub departure: DateTime<Utc>,
+        pub duration: Duration,
+        pub centroid: LatLon,
+        pub radius: f64,                // m, farthest point from the centroid
+        pub altitude: f64,              // m, average, NaN without altitude
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct RestDetector {
+        pub radius: f64,                // m
+        pub min_duration: Duration,
+        pub name_radius: f64,           // m
+        pub summit_range: f64,          // m
+        pub summit_tolerance: f64,      // m, altitude noise of the points around the stop
+    }
+
+    impl RestDetector {
+        pub fn new() -> RestDetector {
+            Self {radius: 50f64, min_duration: Duration::minutes(5), name_radius: 200f64, summit_range: 300f64, summit_tolerance: 5f64}
+        }
+
+        pub fn detect(&self, segment: &TrackSegment) -> Vec<RestStop> {
+            let points = &segment.points;
+            let mut stops = Vec::new();
+            let mut i = 0;
+            while i < points.len() {
+                if !points[i].point.has_time() {
+                    i += 1;
+                    continue;
+                }
+                // Grow the cluster while the next point is within the radius of the centroid
+                let (mut lat, mut lon) = (points[i].point.location.lat, points[i].point.location.lon);
+                let mut j = i;
+                while j + 1 < points.len() && points[j + 1].point.has_time() {
+                    let n = (j - i + 1) as f64;
+                    let centroid = LatLon::new(lat / n, lon / n);
+                    if centroid.great_circle_distance(&points[j + 1].point.location) > self.radius {
+                        break;
+                    }
+                    lat += points[j + 1].point.location.lat;
+                    lon += points[j + 1].point.location.lon;
+                    j += 1;
+                }
+                let duration = points[j].point.time - points[i].point.time;
+                if j > i && duration >= self.min_duration {
+                    stops.push(self.stop(segment, i, j));
+                    i = j + 1;
+                } else {
+                    i += 1;
+                }
+            }
+            stops
+        }
+
+        fn stop(&self, segment: &TrackSegment, start: usize, end: usize) -> RestStop {
+            let run = &segment.points[start..=end];
+            let n = run.len() as f64;
+            let centroid = LatLon::new(run.iter().map(|p| p.point.location.lat).sum::<f64>() / n,
+                                       run.iter().map(|p| p.point.location.lon).sum::<f64>() / n);
+            let altitudes: Vec<f64> = run.iter().map(|p| p.point.altitude).filter(|h| !h.is_nan()).collect();
+            RestStop {
+                start,
+                end,
+                arrival: run[0].point.time,
+                departure: run[run.len() - 1].point.time,
+                duration: run[run.len() - 1].point.time - run[0].point.time,
+                centroid,
+                radius: run.iter().map(|p| centroid.great_circle_distance(&p.point.location)).fold(0f64, f64::max),
+                altitude: if altitudes.is_empty() { f64::NAN } else { altitudes.iter().sum::<f64>() / altitudes.len() as f64 },
+            }
+        }
+
+        // Waypoints at the rest stops, named after the known waypoints (huts, summits, ...) nearby
+        pub fn waypoints(&self, segment: &TrackSegment, known: &[TrackPoint]) -> Vec<TrackPoint> {
+            let stops = self.detect(segment);
+            let mut along = Vec::with_capacity(segment.points.len());
+            let mut d = 0f64;
+            for p in &segment.points {
+                d += p.distance();
+                along.push(d);
+            }
+
+            stops.iter().enumerate().map(|(n, s)| {
+                let mut p = TrackPoint::new(s.centroid.lat, s.centroid.lon);
+                p.altitude = s.altitude;
+                p.time = s.arrival;
+                p.comment = format!("Rest {} min", s.duration.num_minutes());
+                let nearest = known.iter()
+                    .filter(|k| !k.name.is_empty())
+                    .map(|k| (k, s.centroid.great_circle_distance(&k.location)))
+                    .filter(|(_, d)| *d <= self.name_radius)
+                    .min_by(|a, b| a.1.total_cmp(&b.1));
+                let known_type = nearest.and_then(|(k, _)| {
+                    k.point_type.iter().copied().find(|t| *t == PointType::Hut || *t == PointType::Summit)
+                });
+                p.name = match nearest {
+                    Some((k, _)) => { k.name.clone() }
+                    None => { format!("Rest {}", n + 1) }
+                };
+                p.point_type = vec![known_type.unwrap_or_else(|| {
+                    if self.is_summit(segment, &along, s) { PointType::Summit } else { PointType::Note }
+                })];
+                p
+            }).collect()
+        }
+
+        // No point within summit_range along the track before and after the stop is higher by summit_tolerance
+        fn is_summit(&self, segment: &TrackSegment, along: &[f64], stop: &RestStop) -> bool {
+            if stop.altitude.is_nan() {
+                return false;
+            }
+            let (from, to) = (along[stop.start] - self.summit_range, along[stop.end] + self.summit_range);
+            let highest = (0..segment.points.len())
+                .filter(|i| along[*i] >= from && along[*i] <= to)
+                .map(|i| segment.points[i].point.altitude)
+                .fold(f64::NAN, f64::max);
+            // Both sides of the stop have to be in the range
+            along[stop.start] > 0f64 && along[stop.end] < along[along.len() - 1] && highest <= stop.altitude + self.summit_tolerance
+        }
+    }
+
+    impl Track {
+        // Add the rest stop waypoints of every segment, named from the waypoints and the named points of the track
+        // Returns the number of the waypoints added
+        pub fn add_rest_waypoints(self: &mut Self, detector: &RestDetector) -> usize {
+            let mut known = self.waypoints.clone();
+            for r in &self.routes {
+                for s in &r.segments {
+                    known.extend(s.points.iter().filter(|p| !p.point.name.is_empty()).map(|p| p.point.clone()));
+                }
+            }
+            let mut points = Vec::new();
+            for r in &self.routes {
+                for s in &r.segments {
+                    points.extend(detector.waypoints(s, &known));
+                }
+            }
+            let n = points.len();
+            for p in points {
+                self.add_waypoint(p);
+            }
+            n
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use chrono::TimeZone;
+
+        #[test]
+        fn rest_stops() {
+            // Walk 10 minutes, 20 minutes at the hut, walk up 10 minutes, 15 minutes at the top, walk down 10 minutes
+            let start = Utc.with_ymd_and_hms(2023, 8, 1, 6, 0, 0).unwrap();
+            let mut segment = TrackSegment::new();
+            let (mut lat, mut altitude) = (35.0, 1000f64);
+            for i in 0..65 {
+                let (step, climb) = match i {
+                    0..=9 => { (0.0006, 10f64) }
+                    10..=29 => { (0f64, 0f64) }
+                    30..=39 => { (0.0006, 20f64) }
+                    40..=54 => { (0f64, 0f64) }
+                    _ => { (0.0006, -20f64) }
+                };
+                lat += step;
+                altitude += climb;
+                // GPS jitter of a few metres while resting
+                let jitter = if step == 0f64 { 0.00002 * (i % 3) as f64 } else { 0f64 };
+                let mut p = TrackPoint::new(lat + jitter, 139.0);
+                p.altitude = altitude;
+                p.time = start + Duration::minutes(i);
+                segment.add_point(p);
+            }
+            let detector = RestDetector::new();
+            let stops = detector.detect(&segment);
+            assert_eq!(stops.len(), 2);
+            assert_eq!(stops[0].arrival, start + Duration::minutes(9));
+            assert_eq!(stops[0].duration, Duration::minutes(20));
+            assert_eq!(stops[1].duration, Duration::minutes(15));
+            assert!(stops[0].radius < 10f64);
+
+            let mut hut = TrackPoint::new(35.0061, 139.0005);
+            hut.name = String::from("Kita hut");
+            hut.point_type = vec![PointType::Hut];
+            let points = detector.waypoints(&segment, &[hut]);
+            assert_eq!(points[0].name, "Kita hut");
+            assert!(points[0].point_type == vec![PointType::Hut]);
+            assert_eq!(points[1].name, "Rest 2");
+            assert!(points[1].point_type == vec![PointType::Summit]);
+        }
+    }
+}
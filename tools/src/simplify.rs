@@ -0,0 +1,264 @@
+
+//
+// Track simplification tools
+//
+// [DouglasPeucker] tolerance: largest distance (m) of a removed point from the simplified line
+// [VisvalingamWhyatt] tolerance: square root of the smallest effective triangle area (m) kept
+//
+// The error is measured on the local plane (east, north) in metre, with the altitude as the 3rd axis
+// for the 3D variants. The first and last points, points with a name, comment or point type are always kept,
+// and the 3D variants keep the highest and lowest points as well.
+//
+pub mod simplify {
+    use std::cmp::Ordering;
+    use std::collections::BinaryHeap;
+    use crate::geodesic::geodesic::Ellipsoid;
+    use crate::geometry::geometry_core::{dot, wrap_lon};
+    use crate::gpx::gpx::{PointType, TrackSegment};
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Method {
+        DouglasPeucker,
+        VisvalingamWhyatt,
+    }
+
+    // Tolerance(m) or the number of points to keep
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Target {
+        Tolerance(f64),
+        Count(usize),
+    }
+
+    // Entry of the priority queues, ordered by the value
+    struct Candidate {
+        value: f64,
+        index: usize,
+        start: usize,
+        end: usize,
+    }
+
+    impl PartialEq for Candidate {
+        fn eq(&self, other: &Self) -> bool {
+            self.cmp(other) == Ordering::Equal
+        }
+    }
+
+    impl Eq for Candidate {}
+
+    impl PartialOrd for Candidate {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for Candidate {
+        fn cmp(&self, other: &Self) -> Ordering {
+            self.value.total_cmp(&other.value).then(other.index.cmp(&self.index))
+        }
+    }
+
+    impl TrackSegment {
+        pub fn simplify(&self, method: Method, target: Target) -> TrackSegment {
+            self.simplified(&self.simplify_indices(method, target, false))
+        }
+
+        // Altitude aware, keeps the highest and lowest points
+        pub fn simplify_3d(&self, method: Method, target: Target) -> TrackSegment {
+            self.simplified(&self.simplify_indices(method, target, true))
+        }
+
+        // Indices of the points to keep in ascending order
+        pub fn simplify_indices(&self, method: Method, target: Target, three_d: bool) -> Vec<usize> {
+            let n = self.points.len();
+            if n <= 2 {
+                return (0..n).collect();
+            }
+            let xyz = self.local_xyz(three_d);
+            let keep = self.fixed_points(three_d);
+            let mut kept = match method {
+                Method::DouglasPeucker => { douglas_peucker(&xyz, &keep, target) }
+                Method::VisvalingamWhyatt => { visvalingam_whyatt(&xyz, &keep, target) }
+            };
+            kept.sort_unstable();
+            kept
+        }
+
+        fn simplified(&self, indices: &[usize]) -> TrackSegment {
+            let mut segment = TrackSegment::new();
+            segment.name = self.name.clone();
+            segment.comment = self.comment.clone();
+            segment.set_distance_model(self.distance_model());
+            segment.set_vertical_datum(self.vertical_datum());
+            for i in indices {
+                segment.add_point(self.points[*i].point.clone());
+            }
+            segment
+        }
+
+        // Points which must survive the simplification
+        fn fixed_points(&self, three_d: bool) -> Vec<bool> {
+            let n = self.points.len();
+            let mut keep: Vec<bool> = self.points.iter().map(|p| {
+                !p.point.name.is_empty() || !p.point.comment.is_empty()
+                    || p.point.point_type.iter().any(|t| *t != PointType::None)
+            }).collect();
+            keep[0] = true;
+            keep[n - 1] = true;
+            if three_d {
+                let altitudes = self.points.iter().enumerate().filter(|(_, p)| !p.point.altitude.is_nan());
+                let highest = altitudes.clone().max_by(|a, b| a.1.point.altitude.total_cmp(&b.1.point.altitude));
+                let lowest = altitudes.min_by(|a, b| a.1.point.altitude.total_cmp(&b.1.point.altitude));
+                for (i, _) in highest.into_iter().chain(lowest) {
+                    keep[i] = true;
+                }
+            }
+            keep
+        }
+
+        // East, north (m) from the first point on the plane, and the altitude (m) for 3D
+        fn local_xyz(&self, three_d: bool) -> Vec<(f64, f64, f64)> {
+            let radius = Ellipsoid::WGS84.mean_radius();
+            let origin = self.points[0].point.location;
+            let scale = origin.lat.to_radians().cos();
+            self.points.iter().map(|p| {
+                let l = &p.point.location;
+                let dlon = wrap_lon(l.lon - origin.lon);
+                let z = if three_d && !p.point.altitude.is_nan() { p.point.altitude } else { 0f64 };
+                (dlon.to_radians() * scale * radius, (l.lat - origin.lat).to_radians() * radius, z)
+            }).collect()
+        }
+    }
+
+    fn sub(a: (f64, f64, f64), b: (f64, f64, f64)) -> (f64, f64, f64) {
+        (a.0 - b.0, a.1 - b.1, a.2 - b.2)
+    }
+
+    // Distance from p to the line segment a - b
+    fn segment_distance(p: (f64, f64, f64), a: (f64, f64, f64), b: (f64, f64, f64)) -> f64 {
+        let ab = sub(b, a);
+        let ap = sub(p, a);
+        let l = dot(ab, ab);
+        let t = if l == 0f64 { 0f64 } else { (dot(ap, ab) / l).clamp(0f64, 1f64) };
+        let d = sub(ap, (ab.0 * t, ab.1 * t, ab.2 * t));
+        dot(d, d).sqrt()
+    }
+
+    fn triangle_area(a: (f64, f64, f64), b: (f64, f64, f64), c: (f64, f64, f64)) -> f64 {
+        let u = sub(b, a);
+        let v = sub(c, a);
+        let w = (u.1 * v.2 - u.2 * v.1, u.2 * v.0 - u.0 * v.2, u.0 * v.1 - u.1 * v.0);
+        dot(w, w).sqrt() / 2f64
+    }
+
+    fn farthest(xyz: &[(f64, f64, f64)], start: usize, end: usize) -> Option<Candidate> {
+        (start + 1..end)
+            .map(|i| Candidate{value: segment_distance(xyz[i], xyz[start], xyz[end]), index: i, start, end})
+            .max()
+    }
+
+    // Split the ranges at the farthest point until the tolerance or the count is satisfied
+    fn douglas_peucker(xyz: &[(f64, f64, f64)], keep: &[bool], target: Target) -> Vec<usize> {
+        let mut kept: Vec<usize> = (0..xyz.len()).filter(|i| keep[*i]).collect();
+        let mut heap = BinaryHeap::new();
+        for w in kept.windows(2) {
+            heap.extend(farthest(xyz, w[0], w[1]));
+        }
+        while let Some(c) = heap.pop() {
+            let done = match target {
+                Target::Tolerance(tolerance) => { c.value <= tolerance }
+                Target::Count(count) => { kept.len() >= count }
+            };
+            if done {
+                break;
+            }
+            kept.push(c.index);
+            heap.extend(farthest(xyz, c.start, c.index));
+            heap.extend(farthest(xyz, c.index, c.end));
+        }
+        kept
+    }
+
+    // Remove the point of the smallest effective area one by one
+    fn visvalingam_whyatt(xyz: &[(f64, f64, f64)], keep: &[bool], target: Target) -> Vec<usize> {
+        let n = xyz.len();
+        let mut prev: Vec<usize> = (0..n).map(|i| i.saturating_sub(1)).collect();
+        let mut next: Vec<usize> = (0..n).map(|i| (i + 1).min(n - 1)).collect();
+        let mut removed = vec![false; n];
+        let mut area = vec![f64::INFINITY; n];
+        let mut heap = BinaryHeap::new();
+        for i in 1..n - 1 {
+            if !keep[i] {
+                area[i] = triangle_area(xyz[i - 1], xyz[i], xyz[i + 1]);
+                heap.push(std::cmp::Reverse(Candidate{value: area[i], index: i, start: i - 1, end: i + 1}));
+            }
+        }
+        let mut count = n;
+        let mut last = 0f64;
+        while let Some(std::cmp::Reverse(c)) = heap.pop() {
+            if removed[c.index] || c.value != area[c.index] {
+                // Outdated entry
+                continue;
+            }
+            let done = match target {
+                Target::Tolerance(tolerance) => { c.value >= tolerance * tolerance }
+                Target::Count(target) => { count <= target }
+            };
+            if done {
+                break;
+            }
+            removed[c.index] = true;
+            count -= 1;
+            // An area never gets smaller than the one removed before, keeps the order of removal stable
+            last = last.max(c.value);
+            let (p, q) = (prev[c.index], next[c.index]);
+            next[p] = q;
+            prev[q] = p;
+            for i in [p, q] {
+                if !keep[i] {
+                    area[i] = triangle_area(xyz[prev[i]], xyz[i], xyz[next[i]]).max(last);
+                    heap.push(std::cmp::Reverse(Candidate{value: area[i], index: i, start: prev[i], end: next[i]}));
+                }
+            }
+        }
+        (0..n).filter(|i| !removed[*i]).collect()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::gpx::gpx::TrackPoint;
+
+        // Zig-zag of 10 m amplitude along a 1 km line toward east, climbing to the peak in the middle
+        fn zigzag() -> TrackSegment {
+            let mut segment = TrackSegment::new();
+            for i in 0..=100 {
+                let lat = 35.0 + if i % 2 == 0 { 0f64 } else { 0.00009 };
+                let mut p = TrackPoint::new(lat, 139.0 + i as f64 * 0.0001);
+                p.altitude = 150f64 - (i as f64 - 50f64).abs();
+                if i == 31 {
+                    p.set_name("Junction".to_string());
+                }
+                segment.add_point(p);
+            }
+            segment
+        }
+
+        #[test]
+        fn simplify() {
+            let segment = zigzag();
+            // Effective area of the zig-zag grows with the base, VW needs a larger tolerance
+            for (method, tolerance) in [(Method::DouglasPeucker, 20f64), (Method::VisvalingamWhyatt, 100f64)] {
+                let s = segment.simplify_indices(method, Target::Tolerance(tolerance), false);
+                assert_eq!(s, vec![0, 31, 100]);
+                let s = segment.simplify_indices(method, Target::Tolerance(tolerance), true);
+                assert_eq!(s, vec![0, 31, 50, 100]);
+                let s = segment.simplify_indices(method, Target::Tolerance(1f64), false);
+                assert_eq!(s.len(), 101);
+                let s = segment.simplify(method, Target::Count(10));
+                assert_eq!(s.points.len(), 10);
+                assert_eq!(s.points[0].point.location.lon, 139.0);
+                assert!(s.points.iter().any(|p| p.point.name == "Junction"));
+            }
+        }
+    }
+}
@@ -0,0 +1,210 @@
+
+//
+// Spatial index tools
+//
+// [PointIndex] grid index over track points for nearest neighbour and range queries.
+//              Results refer to points by track / route / segment / point index.
+//
+pub mod spatial_index {
+    use std::collections::HashMap;
+    use crate::geometry::geometry_core::{wrap_lon, Area, LatLon};
+    use crate::gpx::gpx::{Track, TrackSegment};
+
+    const DEGREE: f64 = 111195f64;    // Length of 1 degree of latitude (m) on the mean radius sphere
+
+    // Position of a point in the library of tracks
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct PointRef {
+        pub track: usize,
+        pub route: usize,
+        pub segment: usize,
+        pub point: usize,
+    }
+
+    // #PointIndex
+    //
+    // Grid index over track points. The grid cells have the same size in degree for latitude and longitude,
+    // so a cell is narrower in metre toward the poles, queries take care of it.
+    // Distances are great circle distances in metre.
+    pub struct PointIndex {
+        cell: f64,      // Cell size in degree
+        columns: i64,   // Number of cells around the globe
+        cells: HashMap<(i64, i64), Vec<(PointRef, LatLon)>>,
+        len: usize,
+    }
+
+    impl PointIndex {
+        // cell_size: cell size in metre, around the typical query radius works well
+        pub fn new(cell_size: f64) -> PointIndex {
+            let cell = (cell_size / DEGREE).clamp(1e-6, 90f64);
+            Self {cell, columns: (360f64 / cell).ceil() as i64, cells: HashMap::new(), len: 0}
+        }
+
+        pub fn from_segment(segment: &TrackSegment, cell_size: f64) -> PointIndex {
+            let mut index = PointIndex::new(cell_size);
+            index.add_segment(segment, 0, 0, 0);
+            index
+        }
+
+        pub fn from_tracks(tracks: &[Track], cell_size: f64) -> PointIndex {
+            let mut index = PointIndex::new(cell_size);
+            for (t, track) in tracks.iter().enumerate() {
+                for (r, route) in track.routes.iter().enumerate() {
+                    for (s, segment) in route.segments.iter().enumerate() {
+                        index.add_segment(segment, t, r, s);
+                    }
+                }
+            }
+            index
+        }
+
+        pub fn add_segment(self: &mut Self, segment: &TrackSegment, track: usize, route: usize, index: usize) {
+            for (i, p) in segment.points.iter().enumerate() {
+                self.insert(PointRef{track, route, segment: index, point: i}, p.point.location);
+            }
+        }
+
+        pub fn insert(self: &mut Self, r: PointRef, location: LatLon) {
+            let key = self.key(&location);
+            self.cells.entry(key).or_default().push((r, location));
+            self.len += 1;
+        }
+
+        pub fn len(&self) -> usize {
+            self.len
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.len == 0
+        }
+
+        fn key(&self, l: &LatLon) -> (i64, i64) {
+            ((l.lat / self.cell).floor() as i64, ((l.lon + 180f64) / self.cell).floor() as i64 % self.columns)
+        }
+
+        // Cells overlapping the latitude range and the longitude range (west > east wraps).
+        // When the box has more cells than the index, the occupied cells are scanned instead,
+        // so a box around the globe with small cells does not look up every empty cell.
+        fn cells_in(&self, north: f64, west: f64, south: f64, east: f64, whole: bool) -> Vec<&Vec<(PointRef, LatLon)>> {
+            let (top, bottom) = ((north / self.cell).floor() as i64, (south / self.cell).floor() as i64);
+            let (w, span) = if whole {
+                (0, self.columns - 1)
+            } else {
+                let w = ((west + 180f64) / self.cell).floor() as i64;
+                let mut e = ((east + 180f64) / self.cell).floor() as i64;
+                if e < w {
+                    e += self.columns;
+                }
+                (w, (e - w).min(self.columns - 1))
+            };
+            let rows = (top - bottom + 1).max(0);
+            if rows.saturating_mul(span + 1) > self.cells.len() as i64 {
+                return self.cells.iter()
+                    .filter(|((r, c), _)| *r >= bottom && *r <= top && (c - w).rem_euclid(self.columns) <= span)
+                    .map(|(_, v)| v)
+                    .collect();
+            }
+            (bottom..=top).flat_map(|r| {
+                (0..=span).filter_map(move |k| self.cells.get(&(r, (w + k).rem_euclid(self.columns))))
+            }).collect()
+        }
+
+        // Points within the radius (m), nearest first
+        pub fn within_radius(&self, location: &LatLon, radius: f64) -> Vec<(PointRef, f64)> {
+            let dlat = radius / DEGREE;
+            let north = (location.lat + dlat).min(90f64);
+            let south = (location.lat - dlat).max(-90f64);
+            let lat = north.abs().max(south.abs());
+            let whole = lat >= 89.9;
+            let dlon = if whole { 180f64 } else { dlat / lat.to_radians().cos() };
+            let whole = whole || dlon >= 180f64;
+            let west = wrap_lon(location.lon - dlon);
+            let east = wrap_lon(location.lon + dlon);
+
+            let mut list: Vec<(PointRef, f64)> = self.cells_in(north, west, south, east, whole).into_iter().flatten()
+                .map(|(r, l)| (*r, location.great_circle_distance(l)))
+                .filter(|(_, d)| *d <= radius)
+                .collect();
+            list.sort_by(|a, b| a.1.total_cmp(&b.1));
+            list
+        }
+
+        // k nearest points, nearest first, all the points when k is more than the index has
+        pub fn nearest(&self, location: &LatLon, k: usize) -> Vec<(PointRef, f64)> {
+            let k = k.min(self.len);
+            if k == 0 {
+                return Vec::new();
+            }
+            let mut radius = self.cell * DEGREE;
+            loop {
+                let mut list = self.within_radius(location, radius);
+                if list.len() >= k || radius > 2f64 * 20037509f64 {
+                    list.truncate(k);
+                    return list;
+                }
+                radius *= 2f64;
+            }
+        }
+
+        pub fn within_area(&self, area: &Area) -> Vec<PointRef> {
+            if !area.is_valid() {
+                return Vec::new();
+            }
+            let whole = area.span().1 >= 360f64;
+            self.cells_in(area.north(), area.west(), area.south(), area.east(), whole).into_iter().flatten()
+                .filter(|(_, l)| area.contains(l))
+                .map(|(r, _)| *r)
+                .collect()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::gpx::gpx::TrackPoint;
+
+        #[test]
+        fn queries() {
+            let mut segment = TrackSegment::new();
+            for i in 0..100 {
+                segment.add_point(TrackPoint::new(35.0 + i as f64 * 0.0005, 139.0));
+            }
+            let index = PointIndex::from_segment(&segment, 100f64);
+            assert_eq!(index.len(), 100);
+
+            let l = LatLon::new(35.0101, 139.0001);
+            let nearest = index.nearest(&l, 3);
+            assert_eq!(nearest.iter().map(|x| x.0.point).collect::<Vec<usize>>(), vec![20, 21, 19]);
+            // Brute force agrees
+            let brute = segment.points.iter().filter(|p| p.point.location.great_circle_distance(&l) <= 200f64).count();
+            assert_eq!(index.within_radius(&l, 200f64).len(), brute);
+            assert_eq!(index.within_area(&Area::new(35.0049, 138.9, 35.0, 139.1)).len(), 10);
+
+            // Across the 180 degree meridian
+            let mut index = PointIndex::new(1000f64);
+            let r = PointRef{track: 1, route: 0, segment: 2, point: 3};
+            index.insert(r, LatLon::new(0f64, 179.999));
+            assert_eq!(index.nearest(&LatLon::new(0f64, -179.999), 1)[0].0, r);
+        }
+
+        #[test]
+        fn far_queries() {
+            // 100m cells, a query 8000km away and more points asked than indexed
+            let mut segment = TrackSegment::new();
+            for i in 0..10 {
+                segment.add_point(TrackPoint::new(35.0 + i as f64 * 0.001, 139.0));
+            }
+            let index = PointIndex::from_segment(&segment, 100f64);
+            let far = LatLon::new(-40.0, 139.0);
+            let nearest = index.nearest(&far, 1);
+            assert_eq!(nearest[0].0.point, 0);
+            assert!((nearest[0].1 - 75f64 * DEGREE).abs() < 1000f64);
+            let all = index.nearest(&LatLon::new(35.0041, 139.0), 50);
+            assert_eq!(all.len(), 10);
+            assert_eq!(all[0].0.point, 4);
+            assert!(all.windows(2).all(|w| w[0].1 <= w[1].1));
+            assert_eq!(index.within_radius(&far, 30000e3).len(), 10);
+            assert!(PointIndex::new(100f64).nearest(&far, 3).is_empty());
+        }
+    }
+}
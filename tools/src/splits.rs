@@ -0,0 +1,270 @@
+//
+// Split tools
+//
+// -- Split by --
+// [Distance] every given distance (m) along the track, KILOMETRE / MILE or any length, the last split is the rest
+// [Time] every given duration from the first point with time, points without time are passed over
+// [Waypoints] at the points with a name or a point type (huts, summits, ...)
+//
+// The boundaries are interpolated between the points, the distance is along the track (m) as TrackSegment::distance.
+// Gain and loss are the sum of the altitude differences in the split, averages are taken over the points in the split.
+//
+pub mod splits {
+    use chrono::{DateTime, Duration, Utc};
+    use crate::geometry::geometry_core::LatLon;
+    use crate::gpx::gpx::{is_known_time, seconds, PointType, TrackSegment};
+
+    pub const KILOMETRE: f64 = 1000f64;
+    pub const MILE: f64 = 1609.344;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum SplitBy {
+        Distance(f64),
+        Time(Duration),
+        Waypoints,
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct Split {
+        pub start: LatLon,
+        pub end: LatLon,
+        pub start_distance: f64,        // m from the first point
+        pub distance: f64,              // m
+        pub start_time: DateTime<Utc>,  // MIN_UTC when unknown
+        pub end_time: DateTime<Utc>,
+        pub duration: Duration,         // zero when unknown
+        pub pace: f64,                  // s/km, NaN when unknown
+        pub gain: f64,                  // m
+        pub loss: f64,                  // m
+        pub heart_rate: f64,            // NaN when no point has the value
+        pub cadence: f64,
+        pub temperature: f64,
+    }
+
+    // Along track distance, time (s from t0) and altitude of the points
+    struct Profile {
+        along: Vec<f64>,
+        time: Vec<f64>,
+        t0: DateTime<Utc>,
+    }
+
+    impl Profile {
+        fn new(segment: &TrackSegment) -> Profile {
+            let t0 = segment.points.iter().map(|p| p.point.time)
+                .find(is_known_time)
+                .unwrap_or(DateTime::<Utc>::MIN_UTC);
+            let mut along = Vec::with_capacity(segment.points.len());
+            let mut d = 0f64;
+            for p in &segment.points {
+                d += p.distance();
+                along.push(d);
+            }
+            let mut time: Vec<f64> = segment.points.iter().map(|p| {
+                if !p.point.has_time() {
+                    f64::NAN
+                } else {
+                    seconds(&(p.point.time - t0))
+                }
+            }).collect();
+            // Points without time between points with time take the time interpolated on the distance
+            let known: Vec<usize> = (0..time.len()).filter(|i| !time[*i].is_nan()).collect();
+            for w in known.windows(2) {
+                let (a, b) = (w[0], w[1]);
+                let leg = along[b] - along[a];
+                for i in a + 1..b {
+                    let f = if leg > 0f64 { (along[i] - along[a]) / leg } else { 0f64 };
+                    time[i] = time[a] + (time[b] - time[a]) * f;
+                }
+            }
+            Self {along, time, t0}
+        }
+
+        // Point index i and fraction on the leg i - 1 to i at the distance
+        fn locate(&self, distance: f64) -> (usize, f64) {
+            let i = self.along.partition_point(|d| *d < distance).clamp(1, self.along.len() - 1);
+            let leg = self.along[i] - self.along[i - 1];
+            let f = if leg > 0f64 { ((distance - self.along[i - 1]) / leg).clamp(0f64, 1f64) } else { 1f64 };
+            (i, f)
+        }
+
+        fn time_at(&self, distance: f64) -> f64 {
+            let (i, f) = self.locate(distance);
+            self.time[i - 1] + (self.time[i] - self.time[i - 1]) * f
+        }
+
+        // Distance where the time passes t (s from t0)
+        fn distance_at_time(&self, t: f64) -> Option<f64> {
+            for i in 1..self.time.len() {
+                let (t1, t2) = (self.time[i - 1], self.time[i]);
+                if t1 <= t && t < t2 {
+                    return Some(self.along[i - 1] + (self.along[i] - self.along[i - 1]) * (t - t1) / (t2 - t1));
+                }
+            }
+            None
+        }
+
+        fn datetime(&self, t: f64) -> DateTime<Utc> {
+            if t.is_nan() {
+                DateTime::<Utc>::MIN_UTC
+            } else {
+                self.t0 + Duration::milliseconds((t * 1000f64).round() as i64)
+            }
+        }
+    }
+
+    impl TrackSegment {
+        pub fn splits(&self, by: SplitBy) -> Vec<Split> {
+            if self.points.len() < 2 {
+                return Vec::new();
+            }
+            let profile = Profile::new(self);
+            let total = profile.along[profile.along.len() - 1];
+            let mut boundaries = vec![0f64];
+            match by {
+                SplitBy::Distance(step) => {
+                    if step > 0f64 {
+                        let mut d = step;
+                        while d < total {
+                            boundaries.push(d);
+                            d += step;
+                        }
+                    }
+                }
+                SplitBy::Time(step) => {
+                    let step = seconds(&step);
+                    if step > 0f64 {
+                        let mut t = step;
+                        while let Some(d) = profile.distance_at_time(t) {
+                            boundaries.push(d);
+                            t += step;
+                        }
+                    }
+                }
+                SplitBy::Waypoints => {
+                    for (i, p) in self.points.iter().enumerate() {
+                        if !p.point.name.is_empty() || p.point.point_type.iter().any(|t| *t != PointType::None) {
+                            boundaries.push(profile.along[i]);
+                        }
+                    }
+                }
+            }
+            boundaries.push(total);
+            boundaries.dedup_by(|b, a| *b <= *a);
+
+            boundaries.windows(2).map(|w| self.split_between(&profile, w[0], w[1])).collect()
+        }
+
+        fn split_between(&self, profile: &Profile, from: f64, to: f64) -> Split {
+            let location = |d: f64| {
+                let (i, f) = profile.locate(d);
+                self.points[i - 1].point.location.intermediate(&self.points[i].point.location, f)
+            };
+            let altitude = |d: f64| {
+                let (i, f) = profile.locate(d);
+                let (a, b) = (self.points[i - 1].point.altitude, self.points[i].point.altitude);
+                a + (b - a) * f
+            };
+
+            // Points from the start boundary up to the end boundary, the last split includes the last point
+            let last_split = to >= profile.along[profile.along.len() - 1];
+            let inside: Vec<usize> = (0..self.points.len())
+                .filter(|i| profile.along[*i] >= from && (profile.along[*i] < to || last_split))
+                .collect();
+            // Altitudes at the boundaries and the points between
+            let mut altitudes = vec![altitude(from)];
+            altitudes.extend(inside.iter().map(|i| self.points[*i].point.altitude));
+            altitudes.push(altitude(to));
+            let (mut gain, mut loss) = (0f64, 0f64);
+            let mut last = f64::NAN;
+            for h in altitudes.into_iter().filter(|h| !h.is_nan()) {
+                if !last.is_nan() {
+                    if h > last { gain += h - last } else { loss += last - h }
+                }
+                last = h;
+            }
+
+            let average = |value: &dyn Fn(usize) -> f64| {
+                let v: Vec<f64> = inside.iter().map(|i| value(*i)).filter(|x| !x.is_nan()).collect();
+                if v.is_empty() { f64::NAN } else { v.iter().sum::<f64>() / v.len() as f64 }
+            };
+
+            let (t1, t2) = (profile.time_at(from), profile.time_at(to));
+            let duration = if t1.is_nan() || t2.is_nan() {
+                Duration::zero()
+            } else {
+                Duration::milliseconds(((t2 - t1) * 1000f64).round() as i64)
+            };
+            let distance = to - from;
+            Split {
+                start: location(from),
+                end: location(to),
+                start_distance: from,
+                distance,
+                start_time: profile.datetime(t1),
+                end_time: profile.datetime(t2),
+                duration,
+                pace: if t1.is_nan() || t2.is_nan() || distance == 0f64 { f64::NAN } else { (t2 - t1) / distance * 1000f64 },
+                gain,
+                loss,
+                heart_rate: average(&|i| self.points[i].point.heart_rate),
+                cadence: average(&|i| self.points[i].point.cadence),
+                temperature: average(&|i| self.points[i].point.temperature),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use chrono::TimeZone;
+        use crate::gpx::gpx::TrackPoint;
+
+        #[test]
+        fn splits() {
+            // 2.5km toward north at 10 s per 25m (400 s/km), climbing 1m per 25m
+            let start = Utc.with_ymd_and_hms(2023, 8, 1, 6, 0, 0).unwrap();
+            let mut segment = TrackSegment::new();
+            for i in 0..=100 {
+                let mut p = TrackPoint::new(35.0 + i as f64 * 0.000225, 139.0);
+                p.time = start + Duration::seconds(i * 10);
+                p.altitude = i as f64;
+                p.heart_rate = if i < 45 { 120f64 } else { 150f64 };
+                if i == 30 {
+                    p.point_type = vec![PointType::Hut];
+                }
+                segment.add_point(p);
+            }
+            let total = segment.distance();
+
+            let km = segment.splits(SplitBy::Distance(KILOMETRE));
+            assert_eq!(km.len(), 3);
+            assert!((km[0].distance - 1000f64).abs() < 1e-6);
+            assert!((km[2].distance - (total - 2000f64)).abs() < 1e-6);
+            // The boundary falls between the points
+            assert!((km[1].start_distance - 1000f64).abs() < 1e-6);
+            assert!((seconds(&km[0].duration) - 1000f64 / total * 1000f64).abs() < 0.01);
+            assert!((km[0].pace - 1000f64 / total * 1000f64).abs() < 0.01);
+            assert!((km.iter().map(|s| s.gain).sum::<f64>() - 100f64).abs() < 1e-6);
+            assert_eq!(km[0].heart_rate, 120f64);
+            assert_eq!(km[2].heart_rate, 150f64);
+            assert_eq!((km[1].end.lat, km[1].end.lon), (km[2].start.lat, km[2].start.lon));
+
+            let minutes = segment.splits(SplitBy::Time(Duration::minutes(5)));
+            assert_eq!(minutes.len(), 4);
+            assert_eq!(minutes[1].start_time, start + Duration::minutes(5));
+            assert_eq!(minutes[3].duration, Duration::seconds(100));
+
+            // A point without time on the 10 minutes boundary
+            let mut gap = segment.clone();
+            gap.points[60].point.time = DateTime::<Utc>::MIN_UTC;
+            let minutes = gap.splits(SplitBy::Time(Duration::minutes(5)));
+            assert_eq!(minutes.len(), 4);
+            assert_eq!(minutes[2].start_time, start + Duration::minutes(10));
+            assert_eq!(minutes[1].duration, Duration::minutes(5));
+
+            let legs = segment.splits(SplitBy::Waypoints);
+            assert_eq!(legs.len(), 2);
+            assert_eq!(legs[0].end_time, start + Duration::seconds(300));
+        }
+    }
+}
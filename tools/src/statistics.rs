@@ -0,0 +1,181 @@
+//
+// Track statistics tools
+//
+// Times come from TrackPoint.time, DateTime::<Utc>::MIN_UTC is an unknown time.
+// A leg (from the previous point) is moving when its speed is not below the threshold, legs with an unknown
+// time at either end or no time passed are not counted as moving.
+// Stopped time is the rest of the elapsed time, so the gaps between segments count as stopped.
+//
+pub mod statistics {
+    use chrono::{DateTime, Duration, Utc};
+    use crate::gpx::gpx::{is_known_time, seconds, Track, TrackRoute, TrackSegment};
+
+    // Speed (m/s) under which a leg is treated as stopped, about 0.7 km/h
+    pub const STOP_SPEED: f64 = 0.2;
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Statistics {
+        pub start: DateTime<Utc>,       // MIN_UTC when unknown
+        pub end: DateTime<Utc>,
+        pub elapsed: Duration,
+        pub moving: Duration,
+        pub stopped: Duration,
+        pub distance: f64,              // m
+        pub moving_distance: f64,       // m
+        pub average_speed: f64,         // m/s while moving, NaN when never moving
+        pub max_speed: f64,             // m/s, NaN when never moving
+        pub highest: f64,               // m
+        pub lowest: f64,                // m
+    }
+
+    impl Statistics {
+        pub fn new() -> Statistics {
+            Self {
+                start: DateTime::<Utc>::MIN_UTC,
+                end: DateTime::<Utc>::MIN_UTC,
+                elapsed: Duration::zero(),
+                moving: Duration::zero(),
+                stopped: Duration::zero(),
+                distance: 0f64,
+                moving_distance: 0f64,
+                average_speed: f64::NAN,
+                max_speed: f64::NAN,
+                highest: f64::NAN,
+                lowest: f64::NAN,
+            }
+        }
+
+        // Combine with the statistics of the following part
+        pub fn add(self: &mut Self, s: &Statistics) {
+            if is_known_time(&s.start) && (!is_known_time(&self.start) || s.start < self.start) {
+                self.start = s.start;
+            }
+            if is_known_time(&s.end) && (!is_known_time(&self.end) || s.end > self.end) {
+                self.end = s.end;
+            }
+            self.moving = self.moving + s.moving;
+            self.distance += s.distance;
+            self.moving_distance += s.moving_distance;
+            self.max_speed = self.max_speed.max(s.max_speed);
+            self.highest = self.highest.max(s.highest);
+            self.lowest = self.lowest.min(s.lowest);
+            self.update();
+        }
+
+        fn update(self: &mut Self) {
+            self.elapsed = if is_known_time(&self.start) && is_known_time(&self.end) {
+                self.end - self.start
+            } else {
+                Duration::zero()
+            };
+            // Rounding of the moving legs never makes stopped time negative
+            self.stopped = (self.elapsed - self.moving).max(Duration::zero());
+            self.average_speed = if self.moving > Duration::zero() {
+                self.moving_distance / seconds(&self.moving)
+            } else {
+                f64::NAN
+            };
+        }
+    }
+
+    impl TrackSegment {
+        // threshold: speed (m/s) under which the point is stopped, STOP_SPEED is a good start for hiking
+        pub fn statistics(&self, threshold: f64) -> Statistics {
+            let mut s = Statistics::new();
+            s.distance = self.distance();
+            s.highest = self.highest();
+            s.lowest = self.lowest();
+            let times = self.points.iter().map(|p| p.point.time).filter(is_known_time);
+            s.start = times.clone().min().unwrap_or(DateTime::<Utc>::MIN_UTC);
+            s.end = times.max().unwrap_or(DateTime::<Utc>::MIN_UTC);
+
+            for w in self.points.windows(2) {
+                let (t0, t1) = (w[0].point.time, w[1].point.time);
+                if !w[0].point.has_time() || !w[1].point.has_time() || t1 <= t0 {
+                    continue;
+                }
+                let dt = t1 - t0;
+                let speed = w[1].distance() / seconds(&dt);
+                if speed >= threshold {
+                    s.moving = s.moving + dt;
+                    s.moving_distance += w[1].distance();
+                    s.max_speed = s.max_speed.max(speed);
+                }
+            }
+            s.update();
+            s
+        }
+    }
+
+    impl TrackRoute {
+        pub fn statistics(&self, threshold: f64) -> Statistics {
+            let mut s = Statistics::new();
+            for segment in &self.segments {
+                s.add(&segment.statistics(threshold));
+            }
+            s
+        }
+    }
+
+    impl Track {
+        pub fn statistics(&self, threshold: f64) -> Statistics {
+            let mut s = Statistics::new();
+            for route in &self.routes {
+                s.add(&route.statistics(threshold));
+            }
+            s
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use chrono::TimeZone;
+        use crate::gpx::gpx::TrackPoint;
+
+        #[test]
+        fn moving_and_stopped() {
+            // 10 minutes walking 1.1 m/s, 5 minutes rest, 10 minutes walking, one point without time
+            let start = Utc.with_ymd_and_hms(2023, 8, 1, 6, 0, 0).unwrap();
+            let mut segment = TrackSegment::new();
+            let mut lat = 35.0;
+            for i in 0..=25 {
+                if i <= 10 || i > 15 {
+                    lat += 0.0006;
+                }
+                let mut p = TrackPoint::new(lat, 139.0);
+                p.altitude = 1000f64 + i as f64;
+                if i != 20 {
+                    p.time = start + Duration::minutes(i);
+                }
+                segment.add_point(p);
+            }
+            let s = segment.statistics(STOP_SPEED);
+            assert_eq!(s.start, start);
+            assert_eq!(s.end, start + Duration::minutes(25));
+            assert_eq!(s.elapsed, Duration::minutes(25));
+            // Legs 19 - 20 and 20 - 21 have no time
+            assert_eq!(s.moving, Duration::minutes(18));
+            assert_eq!(s.stopped, Duration::minutes(7));
+            assert!((s.average_speed - 66.7 / 60f64).abs() < 0.01);
+            assert!((s.max_speed - s.average_speed).abs() < 0.01);
+            assert_eq!((s.highest, s.lowest), (1025f64, 1000f64));
+
+            // Second segment an hour later
+            let mut route = TrackRoute::new();
+            route.add_segment(&segment);
+            let mut p = TrackPoint::new(36.0, 139.0);
+            p.time = start + Duration::hours(1);
+            let mut later = TrackSegment::new();
+            later.add_point(p);
+            route.add_segment(&later);
+            let r = route.statistics(STOP_SPEED);
+            assert_eq!(r.elapsed, Duration::hours(1));
+            assert_eq!(r.moving, s.moving);
+            assert_eq!(r.stopped, Duration::minutes(42));
+            assert_eq!(route.highest(), 1025f64);
+
+            assert!(TrackSegment::new().statistics(STOP_SPEED).average_speed.is_nan());
+        }
+    }
+}